@@ -0,0 +1,80 @@
+//! Measures what the html set prefilter brings, by checking every page of a stored HTML corpus
+//! with and without it.
+//!
+//!     WAPPALYZER_CORPUS=path/to/pages cargo bench --bench html_prefilter
+//!
+//! Every file of the corpus directory is read as the html of one page.
+
+use std::env;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wappalyzer::wapp::{Engine, RawData};
+
+fn main() {
+    let corpus_dir = match env::var("WAPPALYZER_CORPUS") {
+        Ok(corpus_dir) => corpus_dir,
+        Err(_) => {
+            eprintln!("Set WAPPALYZER_CORPUS to a directory of html pages to run this benchmark");
+            return;
+        }
+    };
+
+    let pages = fs::read_dir(&corpus_dir)
+        .expect("Error reading the corpus directory")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .map(|html| {
+            Arc::new(RawData {
+                url: String::new(),
+                final_url: String::new(),
+                headers: reqwest::header::HeaderMap::new(),
+                cookies: vec![],
+                meta_tags: Default::default(),
+                script_tags: vec![],
+                script_srcs: vec![],
+                html,
+                text: String::new(),
+                css: vec![],
+                stylesheet_hrefs: vec![],
+                links: vec![],
+                js_globals: Default::default(),
+                external_scripts: Default::default(),
+                external_stylesheets: Default::default(),
+                dom: Default::default(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let bytes = pages.iter().map(|page| page.html.len()).sum::<usize>();
+    println!("{} pages, {} bytes of html", pages.len(), bytes);
+
+    let with_set = Engine::embedded();
+    let without_set = Engine::embedded().without_html_prefilter();
+
+    let sequential = run(&without_set, &pages);
+    let prefiltered = run(&with_set, &pages);
+    report("sequential", sequential, pages.len());
+    report("html set", prefiltered, pages.len());
+    println!(
+        "speedup: {:.2}x",
+        sequential.as_secs_f64() / prefiltered.as_secs_f64()
+    );
+}
+
+fn run(engine: &Engine, pages: &[Arc<RawData>]) -> Duration {
+    let start = Instant::now();
+    for page in pages {
+        engine.check_sync(page.clone());
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration, pages: usize) {
+    println!(
+        "{:>12}: {:?} ({:.1} pages/s)",
+        name,
+        elapsed,
+        pages as f64 / elapsed.as_secs_f64()
+    );
+}
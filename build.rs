@@ -9,6 +9,12 @@ fn main() {
     let mut f = File::create(&dest_path).unwrap();
 
     let apps_json_str = include_str!("./apps.json");
-    f.write_all(format!("const APPS_JSON_TEXT : &str = r##\"{}\"##;", apps_json_str).as_bytes())
-        .unwrap();
+    f.write_all(
+        format!(
+            "pub(crate) const APPS_JSON_TEXT : &str = r##\"{}\"##;",
+            apps_json_str
+        )
+        .as_bytes(),
+    )
+    .unwrap();
 }
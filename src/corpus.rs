@@ -0,0 +1,364 @@
+//! Checking a corpus of saved pages with two rulesets, to see what an update of the apps would
+//! change on real pages before rolling it out: which techs are gained and lost, and where.
+
+use crate::wapp::{one_or_more_strings_by_key, Cookie, Engine, RawData};
+use crate::WappError;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, SET_COOKIE};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use url::Url;
+
+/// A page as it was fetched: its headers and html
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SavedPage {
+    /// The URL that was requested
+    pub url: String,
+    /// The URL the page was served from, after following redirects, when it differs from `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    /// The response headers, by name. A header sent more than once has several values.
+    #[serde(default, deserialize_with = "one_or_more_strings_by_key")]
+    pub headers: HashMap<String, Vec<String>>,
+    pub html: String,
+    /// The contents of the page's external scripts, by src (resolved against the page, as in
+    /// `RawData::script_srcs`), when they were saved along with it
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub scripts: HashMap<String, String>,
+    /// The contents of the page's external stylesheets, by href, when they were saved along
+    /// with it
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub stylesheets: HashMap<String, String>,
+}
+
+impl SavedPage {
+    /// Loads a saved page: either a JSON `SavedPage`, or a bare .html file (without headers),
+    /// whose URL is the `file://` URL of the file
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<SavedPage, WappError> {
+        let path = path.as_ref();
+        let read_error = |err: &dyn fmt::Display| {
+            WappError::Other(format!("Error loading {}: {}", path.display(), err))
+        };
+
+        let text = fs::read_to_string(path).map_err(|err| read_error(&err))?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            return serde_json::from_str(&text).map_err(|err| read_error(&err));
+        }
+        let url = fs::canonicalize(path)
+            .ok()
+            .and_then(|path| Url::from_file_path(path).ok())
+            .ok_or_else(|| read_error(&"no file:// URL for the path"))?;
+        Ok(SavedPage {
+            url: String::from(url.as_str()),
+            final_url: None,
+            headers: HashMap::new(),
+            html: text,
+            scripts: HashMap::new(),
+            stylesheets: HashMap::new(),
+        })
+    }
+
+    /// The page, as `check` looks at it. The cookies are those of the `Set-Cookie` headers.
+    pub fn raw_data(&self) -> Result<RawData, WappError> {
+        let page_url = Url::parse(self.final_url.as_ref().unwrap_or(&self.url))
+            .map_err(|err| WappError::Other(format!("Invalid URL of {}: {}", self.url, err)))?;
+
+        let mut headers = HeaderMap::new();
+        for (name, values) in self.headers.iter() {
+            // a header that can't be sent can't be matched either
+            let name = match HeaderName::from_bytes(name.as_bytes()) {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            for value in values {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    headers.append(name.clone(), value);
+                }
+            }
+        }
+        let cookies = headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(parse_set_cookie)
+            .collect();
+
+        let mut raw_data = RawData::new(&self.url, &page_url, headers, cookies, self.html.clone());
+        raw_data.add_assets(self.scripts.clone(), self.stylesheets.clone());
+        Ok(raw_data)
+    }
+}
+
+/// The name and value of a `Set-Cookie` header, without its attributes
+fn parse_set_cookie(header: &str) -> Option<Cookie> {
+    let pair = header.split(';').next()?;
+    let (name, value) = pair.split_at(pair.find('=')?);
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some(Cookie {
+        name: String::from(name),
+        value: String::from(value[1..].trim().trim_matches('"')),
+    })
+}
+
+/// Loads the saved pages of a directory: its .json and .html files, sorted by file name. A file
+/// that can't be read or parsed is skipped, and comes back with the error, which names it.
+pub fn load_corpus<P: AsRef<Path>>(dir: P) -> Result<(Vec<SavedPage>, Vec<WappError>), WappError> {
+    let dir = dir.as_ref();
+    let mut paths = dir
+        .read_dir()
+        .map_err(|err| WappError::Other(format!("Error loading {}: {}", dir.display(), err)))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "json" || ext == "html" || ext == "htm")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut pages = vec![];
+    let mut skipped = vec![];
+    for path in paths {
+        match SavedPage::from_path(&path) {
+            Ok(page) => pages.push(page),
+            Err(err) => skipped.push(err),
+        }
+    }
+    Ok((pages, skipped))
+}
+
+/// How the detections on a corpus differ between two rulesets
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CorpusDiff {
+    /// The number of pages checked
+    pub pages: usize,
+    /// The number of pages where something was gained or lost
+    pub changed_pages: usize,
+    /// The techs that were gained or lost on some page, the most affected first
+    pub techs: Vec<TechDelta>,
+}
+
+/// The pages where a tech is detected with one ruleset and not the other
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TechDelta {
+    pub name: String,
+    /// The URLs of the pages where only the new ruleset detects it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gained: Vec<String>,
+    /// The URLs of the pages where only the old ruleset detects it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lost: Vec<String>,
+}
+
+/// Checks every page with both engines, and collects the techs detected by only one of them. A
+/// page that can't be checked (ex: its URL doesn't parse) is skipped, and comes back with the
+/// error, which names it.
+pub fn compare(pages: &[SavedPage], old: &Engine, new: &Engine) -> (CorpusDiff, Vec<WappError>) {
+    let detected = |engine: &Engine, raw_data: &Arc<RawData>| {
+        engine
+            .check_sync(raw_data.clone())
+            .techs
+            .into_iter()
+            .map(|tech| tech.name)
+            .collect::<BTreeSet<_>>()
+    };
+
+    let mut techs: BTreeMap<String, TechDelta> = BTreeMap::new();
+    let mut changed_pages = 0;
+    let mut skipped = vec![];
+    for page in pages {
+        let raw_data = match page.raw_data() {
+            Ok(raw_data) => Arc::new(raw_data),
+            Err(err) => {
+                skipped.push(err);
+                continue;
+            }
+        };
+        let (before, after) = (detected(old, &raw_data), detected(new, &raw_data));
+        if before == after {
+            continue;
+        }
+        changed_pages += 1;
+        for name in after.difference(&before) {
+            tech_delta(&mut techs, name).gained.push(page.url.clone());
+        }
+        for name in before.difference(&after) {
+            tech_delta(&mut techs, name).lost.push(page.url.clone());
+        }
+    }
+
+    let mut techs = techs.into_values().collect::<Vec<_>>();
+    // stable, so the techs affected on as many pages stay sorted by name
+    techs.sort_by_key(|delta| std::cmp::Reverse(delta.gained.len() + delta.lost.len()));
+    let corpus_diff = CorpusDiff {
+        pages: pages.len() - skipped.len(),
+        changed_pages,
+        techs,
+    };
+    (corpus_diff, skipped)
+}
+
+fn tech_delta<'a>(techs: &'a mut BTreeMap<String, TechDelta>, name: &str) -> &'a mut TechDelta {
+    techs
+        .entry(String::from(name))
+        .or_insert_with(|| TechDelta {
+            name: String::from(name),
+            ..TechDelta::default()
+        })
+}
+
+impl fmt::Display for CorpusDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for tech in self.techs.iter() {
+            writeln!(
+                f,
+                "{} (+{} -{})",
+                tech.name,
+                tech.gained.len(),
+                tech.lost.len()
+            )?;
+            for url in tech.gained.iter() {
+                writeln!(f, "    + {}", url)?;
+            }
+            for url in tech.lost.iter() {
+                writeln!(f, "    - {}", url)?;
+            }
+        }
+        write!(f, "{} of {} pages changed", self.changed_pages, self.pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let old: Engine = r#"{
+            "apps": {
+                "Nginx": { "cats": [1], "website": "", "headers": { "Server": "nginx" } },
+                "Acme": { "cats": [1], "website": "", "html": "acme-old" },
+                "Widget": { "cats": [1], "website": "", "cookies": { "widget_session": "" } }
+            },
+            "categories": { "1": { "name": "Web servers", "priority": 1 } }
+        }"#
+        .parse()
+        .unwrap();
+        let new: Engine = r#"{
+            "apps": {
+                "Nginx": { "cats": [1], "website": "", "headers": { "Server": "nginx" } },
+                "Acme": { "cats": [1], "website": "", "html": "acme-new" },
+                "Widget": { "cats": [1], "website": "", "cookies": { "widget_session": "" } }
+            },
+            "categories": { "1": { "name": "Web servers", "priority": 1 } }
+        }"#
+        .parse()
+        .unwrap();
+
+        let pages: Vec<SavedPage> = serde_json::from_str(
+            r#"[
+                {
+                    "url": "https://a.example/",
+                    "headers": { "server": "nginx/1.18", "set-cookie": ["widget_session=1; Path=/", "other=2"] },
+                    "html": "<p>acme-old</p>"
+                },
+                { "url": "https://b.example/", "html": "<p>acme-new</p>" },
+                { "url": "https://c.example/", "html": "<p>acme-new acme-old</p>" }
+            ]"#,
+        )
+        .unwrap();
+
+        let raw_data = pages[0].raw_data().unwrap();
+        assert_eq!(raw_data.cookies.len(), 2);
+        assert_eq!(raw_data.cookies[0].name, "widget_session");
+
+        let (corpus_diff, skipped) = compare(&pages, &old, &new);
+        assert!(skipped.is_empty());
+        assert_eq!(corpus_diff.pages, 3);
+        assert_eq!(corpus_diff.changed_pages, 2);
+        assert_eq!(
+            corpus_diff.techs,
+            vec![TechDelta {
+                name: String::from("Acme"),
+                gained: vec![String::from("https://b.example/")],
+                lost: vec![String::from("https://a.example/")],
+            }]
+        );
+        assert_eq!(
+            corpus_diff.to_string(),
+            "Acme (+1 -1)\n    + https://b.example/\n    - https://a.example/\n2 of 3 pages changed"
+        );
+
+        let (unchanged, _) = compare(&pages, &old, &old);
+        assert_eq!(unchanged.changed_pages, 0);
+        assert!(unchanged.techs.is_empty());
+    }
+
+    #[test]
+    fn test_compare_skipped() {
+        let old: Engine = r#"{
+            "apps": { "Acme": { "cats": [1], "website": "", "html": "acme-old" } },
+            "categories": { "1": { "name": "CMS", "priority": 1 } }
+        }"#
+        .parse()
+        .unwrap();
+        let new: Engine = r#"{
+            "apps": { "Acme": { "cats": [1], "website": "", "html": "acme-new" } },
+            "categories": { "1": { "name": "CMS", "priority": 1 } }
+        }"#
+        .parse()
+        .unwrap();
+        let pages: Vec<SavedPage> = serde_json::from_str(
+            r#"[
+                { "url": "not a url", "html": "<p>acme-old</p>" },
+                { "url": "https://b.example/", "html": "<p>acme-new</p>" }
+            ]"#,
+        )
+        .unwrap();
+
+        // the page with an invalid URL doesn't stop the others from being compared
+        let (corpus_diff, skipped) = compare(&pages, &old, &new);
+        assert_eq!(corpus_diff.pages, 1);
+        assert_eq!(corpus_diff.changed_pages, 1);
+        assert_eq!(corpus_diff.techs[0].gained, vec!["https://b.example/"]);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].to_string().contains("not a url"));
+    }
+
+    #[test]
+    fn test_load_corpus() {
+        let dir = std::env::temp_dir().join(format!("wappalyzer-corpus-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.html"), "<html>acme</html>").unwrap();
+        fs::write(dir.join("b.json"), r#"{ "url": "https://b.example/" "#).unwrap();
+        fs::write(
+            dir.join("c.json"),
+            r#"{ "url": "https://c.example/", "html": "" }"#,
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a page").unwrap();
+
+        let (pages, skipped) = load_corpus(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].url, "https://c.example/");
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].to_string().contains("b.json"));
+    }
+
+    #[test]
+    fn test_parse_set_cookie() {
+        let cookie =
+            parse_set_cookie("id=\"a3fWa\"; Expires=Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(
+            (cookie.name.as_str(), cookie.value.as_str()),
+            ("id", "a3fWa")
+        );
+        assert!(parse_set_cookie("no-value").is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+
+/// The attributes of a CPE name, in the order of the CPE 2.3 formatted string
+const ATTRIBUTES: usize = 11;
+const PART: usize = 0;
+const VERSION: usize = 3;
+
+/// A CPE (Common Platform Enumeration) name, as used by the NVD to identify software.
+///
+/// apps.json gives them as CPE 2.2 URIs (`cpe:/a:wordpress:wordpress`), which are normalized
+/// to the CPE 2.3 formatted string (`cpe:2.3:a:wordpress:wordpress:*:*:*:*:*:*:*:*`).
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cpe {
+    /// part, vendor, product, version, update, edition, language, sw_edition, target_sw,
+    /// target_hw and other, escaped as in a formatted string, "*" for any value
+    attributes: Vec<String>,
+}
+
+impl Cpe {
+    /// Parses a CPE 2.2 URI or a CPE 2.3 formatted string
+    pub fn parse(cpe: &str) -> Result<Cpe, String> {
+        let attributes = if let Some(formatted) = cpe.strip_prefix("cpe:2.3:") {
+            let attributes = split_formatted(formatted);
+            if attributes.len() != ATTRIBUTES {
+                return Err(format!(
+                    "expected {} attributes in '{}', found {}",
+                    ATTRIBUTES,
+                    cpe,
+                    attributes.len()
+                ));
+            }
+            attributes
+        } else if let Some(uri) = cpe.strip_prefix("cpe:/") {
+            let mut attributes = uri
+                .split(':')
+                .map(uri_to_formatted)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("invalid URI '{}': {}", cpe, err))?;
+            // the URI only has the first 7 attributes, and the trailing ones can be left out
+            if attributes.len() > 7 {
+                return Err(format!("too many attributes in '{}'", cpe));
+            }
+            attributes.resize(ATTRIBUTES, String::from("*"));
+            attributes
+        } else {
+            return Err(format!(
+                "'{}' is neither a CPE 2.2 URI nor a CPE 2.3 name",
+                cpe
+            ));
+        };
+
+        if !["a", "o", "h"].contains(&attributes[PART].as_str()) {
+            return Err(format!(
+                "the part of '{}' should be a, o or h, not '{}'",
+                cpe, attributes[PART]
+            ));
+        }
+        Ok(Cpe { attributes })
+    }
+
+    /// The same name with the given version, unless it already names one
+    pub fn with_version(mut self, version: &str) -> Cpe {
+        if self.attributes[VERSION] == "*" && !version.is_empty() {
+            self.attributes[VERSION] = escape(version);
+        }
+        self
+    }
+}
+
+impl fmt::Display for Cpe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cpe:2.3:{}", self.attributes.join(":"))
+    }
+}
+
+/// Splits a formatted string on the colons that aren't escaped
+fn split_formatted(formatted: &str) -> Vec<String> {
+    let mut attributes = vec![String::new()];
+    let mut chars = formatted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let last = attributes.last_mut().unwrap();
+                last.push(c);
+                last.extend(chars.next());
+            }
+            ':' => attributes.push(String::new()),
+            _ => attributes.last_mut().unwrap().push(c),
+        }
+    }
+    attributes
+}
+
+/// Decodes a URI attribute (percent-encoded) and escapes it for a formatted string
+fn uri_to_formatted(attribute: &str) -> Result<String, String> {
+    match attribute {
+        "" => return Ok(String::from("*")),
+        "-" => return Ok(String::from("-")),
+        _ => (),
+    }
+
+    let mut decoded = String::new();
+    let mut chars = attribute.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            let hex = chars.by_ref().take(2).collect::<String>();
+            let byte = u8::from_str_radix(&hex, 16)
+                .map_err(|_| format!("invalid percent-encoding '%{}'", hex))?;
+            decoded.push(char::from(byte));
+        } else {
+            decoded.push(c);
+        }
+    }
+    Ok(escape(&decoded.to_lowercase()))
+}
+
+/// Escapes a value for a formatted string: everything but letters, digits, '_', '.' and '-'
+/// is quoted with a backslash
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cpe = |cpe: &str| Cpe::parse(cpe).map(|cpe| cpe.to_string());
+
+        assert_eq!(
+            cpe("cpe:/a:wordpress:wordpress"),
+            Ok(String::from(
+                "cpe:2.3:a:wordpress:wordpress:*:*:*:*:*:*:*:*"
+            ))
+        );
+        assert_eq!(
+            cpe("cpe:/a:erlang:erlang%2fotp"),
+            Ok(String::from(
+                "cpe:2.3:a:erlang:erlang\\/otp:*:*:*:*:*:*:*:*"
+            ))
+        );
+        assert_eq!(
+            cpe("cpe:/o:redhat:linux:7.0"),
+            Ok(String::from("cpe:2.3:o:redhat:linux:7.0:*:*:*:*:*:*:*"))
+        );
+        assert_eq!(
+            cpe("cpe:2.3:a:nginx:nginx:*:*:*:*:*:*:*:*"),
+            Ok(String::from("cpe:2.3:a:nginx:nginx:*:*:*:*:*:*:*:*"))
+        );
+        assert!(cpe("cpe:2.3:a:nginx:nginx").is_err());
+        assert!(cpe("cpe:/x:nginx:nginx").is_err());
+        assert!(cpe("nginx").is_err());
+    }
+
+    #[test]
+    fn test_with_version() {
+        let cpe = Cpe::parse("cpe:/a:jquery:jquery").unwrap();
+        assert_eq!(
+            cpe.clone().with_version("1.12.4").to_string(),
+            "cpe:2.3:a:jquery:jquery:1.12.4:*:*:*:*:*:*:*"
+        );
+        assert_eq!(
+            cpe.with_version("2+").to_string(),
+            "cpe:2.3:a:jquery:jquery:2\\+:*:*:*:*:*:*:*"
+        );
+
+        let cpe = Cpe::parse("cpe:/o:redhat:linux:7.0").unwrap();
+        assert_eq!(
+            cpe.with_version("8").to_string(),
+            "cpe:2.3:o:redhat:linux:7.0:*:*:*:*:*:*:*"
+        );
+    }
+}
@@ -0,0 +1,484 @@
+//! What changed between two rulesets, as far as detection goes: the apps, their categories,
+//! their patterns and what they imply, exclude and require.
+
+use crate::wapp::{App, AppsJsonData, DomCheck, Pattern, TechCategory};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RulesetDiff {
+    pub added_apps: Vec<String>,
+    pub removed_apps: Vec<String>,
+    /// The apps in both rulesets whose rules changed, sorted by name
+    pub changed_apps: Vec<AppDiff>,
+    /// The categories that were added, removed, renamed or given another priority
+    pub categories: Vec<CategoryChange>,
+}
+impl RulesetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_apps.is_empty()
+            && self.removed_apps.is_empty()
+            && self.changed_apps.is_empty()
+            && self.categories.is_empty()
+    }
+}
+
+/// The changes to an app that is in both rulesets
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AppDiff {
+    pub name: String,
+    /// The names of the categories of the app, before and after, when they changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub categories: Option<(Vec<String>, Vec<String>)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<PatternChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub implies: Vec<Change>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excludes: Vec<Change>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<Change>,
+    /// The ids of the categories the app requires an app from, as in apps.json
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires_category: Vec<Change>,
+}
+impl AppDiff {
+    fn is_empty(&self) -> bool {
+        self.categories.is_none()
+            && self.patterns.is_empty()
+            && self.implies.is_empty()
+            && self.excludes.is_empty()
+            && self.requires.is_empty()
+            && self.requires_category.is_empty()
+    }
+}
+
+/// A pattern of an app that was added, removed or modified
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PatternChange {
+    /// The apps.json field of the pattern (headers, html, cookies, js, meta, script, ...)
+    pub field: String,
+    /// The header, cookie, meta or js name, or the dom selector and what it checks (ex:
+    /// "#app attributes.href"), for the fields that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub change: Change,
+}
+
+/// A value that was added, removed, or modified. For the fields that are lists, a pattern is
+/// modified when only its tags changed (ex: its `\;version:`), otherwise it was replaced.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Modified { old: String, new: String },
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CategoryChange {
+    Added(TechCategory),
+    Removed(TechCategory),
+    Modified {
+        old: TechCategory,
+        new: TechCategory,
+    },
+}
+
+/// Compares two rulesets
+pub fn diff(old: &AppsJsonData, new: &AppsJsonData) -> RulesetDiff {
+    let mut ruleset_diff = RulesetDiff::default();
+
+    for app in old.apps() {
+        match new.named(app.name()) {
+            Some(new_app) => {
+                let app_diff = diff_app(app, new_app);
+                if !app_diff.is_empty() {
+                    ruleset_diff.changed_apps.push(app_diff);
+                }
+            }
+            None => ruleset_diff.removed_apps.push(String::from(app.name())),
+        }
+    }
+    ruleset_diff.added_apps = new
+        .apps()
+        .into_iter()
+        .filter(|app| old.named(app.name()).is_none())
+        .map(|app| String::from(app.name()))
+        .collect();
+
+    for category in old.categories() {
+        match new.category(category.id) {
+            Some(new_category) if new_category != category => {
+                ruleset_diff.categories.push(CategoryChange::Modified {
+                    old: category,
+                    new: new_category,
+                })
+            }
+            Some(_) => (),
+            None => ruleset_diff
+                .categories
+                .push(CategoryChange::Removed(category)),
+        }
+    }
+    ruleset_diff.categories.extend(
+        new.categories()
+            .into_iter()
+            .filter(|category| old.category(category.id).is_none())
+            .map(CategoryChange::Added),
+    );
+
+    ruleset_diff
+}
+
+fn diff_app(old: &App, new: &App) -> AppDiff {
+    let category_names = |categories: Vec<TechCategory>| {
+        categories
+            .into_iter()
+            .map(|category| category.name)
+            .collect::<Vec<_>>()
+    };
+    let old_categories = category_names(old.categories());
+    let new_categories = category_names(new.categories());
+    let ids = |app: &App| {
+        app.requires_category()
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    AppDiff {
+        name: String::from(old.name()),
+        categories: if old_categories != new_categories {
+            Some((old_categories, new_categories))
+        } else {
+            None
+        },
+        patterns: diff_patterns(old, new),
+        implies: diff_values(old.implies(), new.implies()),
+        excludes: diff_values(old.excludes(), new.excludes()),
+        requires: diff_values(old.requires(), new.requires()),
+        requires_category: diff_values(&ids(old), &ids(new)),
+    }
+}
+
+/// Compares the patterns of each field (and key), in the order of the fields then of the keys
+fn diff_patterns(old: &App, new: &App) -> Vec<PatternChange> {
+    // (field, key) to (old patterns, new patterns)
+    let mut by_field = BTreeMap::<(&str, Option<String>), (Vec<String>, Vec<String>)>::new();
+    for (field, key, value) in keyed_rules(old) {
+        let entry = by_field.entry((field, key)).or_default();
+        entry.0.push(value.clone());
+    }
+    for (field, key, value) in keyed_rules(new) {
+        let entry = by_field.entry((field, key)).or_default();
+        entry.1.push(value.clone());
+    }
+
+    let mut changes = vec![];
+    for ((field, key), (old_values, new_values)) in by_field {
+        let values = if key.is_some() && old_values.len() == 1 && new_values.len() == 1 {
+            // a header, cookie, meta or js name whose only pattern changed
+            if old_values == new_values {
+                vec![]
+            } else {
+                vec![Change::Modified {
+                    old: old_values[0].clone(),
+                    new: new_values[0].clone(),
+                }]
+            }
+        } else {
+            diff_values(&old_values, &new_values)
+        };
+        changes.extend(values.into_iter().map(|change| PatternChange {
+            field: String::from(field),
+            key: key.clone(),
+            change,
+        }));
+    }
+    changes
+}
+
+/// The rules of the app as `App::rules` lists them, but with the dom rules keyed by what they
+/// check as well as by selector, ex: "#app attributes.href"
+fn keyed_rules(app: &App) -> Vec<(&'static str, Option<String>, &String)> {
+    let rules = app
+        .rules()
+        .into_iter()
+        .filter(|(field, _, _)| *field != "dom")
+        .map(|(field, key, value)| (field, key.cloned(), value));
+    let dom_rules = app.dom_rules().into_iter().map(|(selector, check, value)| {
+        let key = match check {
+            DomCheck::Exists => selector.clone(),
+            DomCheck::Text => format!("{} text", selector),
+            DomCheck::Attribute(name) => format!("{} attributes.{}", selector, name),
+            DomCheck::Property(name) => format!("{} properties.{}", selector, name),
+        };
+        ("dom", Some(key), value)
+    });
+    rules.chain(dom_rules).collect()
+}
+
+/// The values that were removed and added, pairing a removed value with an added one that only
+/// differs by its tags (ex: "PHP\;confidence:50" and "PHP") as a modification
+fn diff_values(old: &[String], new: &[String]) -> Vec<Change> {
+    let mut removed = old
+        .iter()
+        .filter(|value| !new.contains(value))
+        .collect::<Vec<_>>();
+    let mut changes = vec![];
+    for value in new.iter().filter(|value| !old.contains(value)) {
+        let regex = Pattern::parse(value).regex;
+        match removed
+            .iter()
+            .position(|old| Pattern::parse(old).regex == regex)
+        {
+            Some(i) => changes.push(Change::Modified {
+                old: removed.remove(i).clone(),
+                new: value.clone(),
+            }),
+            None => changes.push(Change::Added(value.clone())),
+        }
+    }
+    changes.extend(removed.into_iter().cloned().map(Change::Removed));
+    changes
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(value) => write!(f, "+ {}", value),
+            Change::Removed(value) => write!(f, "- {}", value),
+            Change::Modified { old, new } => write!(f, "~ {} -> {}", old, new),
+        }
+    }
+}
+
+impl fmt::Display for CategoryChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let category = |c: &TechCategory| format!("{} {} (priority {})", c.id, c.name, c.priority);
+        match self {
+            CategoryChange::Added(new) => write!(f, "+ {}", category(new)),
+            CategoryChange::Removed(old) => write!(f, "- {}", category(old)),
+            CategoryChange::Modified { old, new } => {
+                write!(f, "~ {} -> {}", category(old), category(new))
+            }
+        }
+    }
+}
+
+impl fmt::Display for RulesetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in self.added_apps.iter() {
+            writeln!(f, "+ {}", name)?;
+        }
+        for name in self.removed_apps.iter() {
+            writeln!(f, "- {}", name)?;
+        }
+        for app in self.changed_apps.iter() {
+            writeln!(f, "~ {}", app.name)?;
+            if let Some((old, new)) = &app.categories {
+                writeln!(
+                    f,
+                    "    categories: {} -> {}",
+                    old.join(", "),
+                    new.join(", ")
+                )?;
+            }
+            for pattern in app.patterns.iter() {
+                let (sign, change) = split_sign(&pattern.change);
+                match &pattern.key {
+                    Some(key) => {
+                        writeln!(f, "    {} {}[{}]: {}", sign, pattern.field, key, change)?
+                    }
+                    None => writeln!(f, "    {} {}: {}", sign, pattern.field, change)?,
+                }
+            }
+            let references = [
+                ("implies", &app.implies),
+                ("excludes", &app.excludes),
+                ("requires", &app.requires),
+                ("requiresCategory", &app.requires_category),
+            ];
+            for (field, changes) in references.iter() {
+                for change in changes.iter() {
+                    let (sign, change) = split_sign(change);
+                    writeln!(f, "    {} {}: {}", sign, field, change)?;
+                }
+            }
+        }
+        if !self.categories.is_empty() {
+            writeln!(f, "categories:")?;
+            for category in self.categories.iter() {
+                writeln!(f, "    {}", category)?;
+            }
+        }
+        write!(
+            f,
+            "{} apps added, {} removed, {} changed, {} categories changed",
+            self.added_apps.len(),
+            self.removed_apps.len(),
+            self.changed_apps.len(),
+            self.categories.len()
+        )
+    }
+}
+
+/// "+ value" into ("+", "value"), to put the field between them
+fn split_sign(change: &Change) -> (&'static str, String) {
+    match change {
+        Change::Added(value) => ("+", value.clone()),
+        Change::Removed(value) => ("-", value.clone()),
+        Change::Modified { old, new } => ("~", format!("{} -> {}", old, new)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old: AppsJsonData = r#"{
+            "categories": {
+                "1": { "name": "CMS", "priority": 1 },
+                "2": { "name": "Blogs", "priority": 1 }
+            },
+            "apps": {
+                "Acme CMS": {
+                    "cats": [1, 2],
+                    "website": "",
+                    "html": ["acme-root", "acme-([\\d.]+)\\;version:\\1"],
+                    "headers": { "X-Powered-By": "Acme", "X-Acme": "" },
+                    "implies": ["PHP\\;confidence:50", "MySQL"]
+                },
+                "Old Blog": { "cats": [2], "website": "", "html": "old-blog" },
+                "PHP": { "cats": [1], "website": "" },
+                "MySQL": { "cats": [1], "website": "" }
+            }
+        }"#
+        .parse()
+        .unwrap();
+        let new: AppsJsonData = r#"{
+            "categories": {
+                "1": { "name": "CMS", "priority": 2 },
+                "3": { "name": "Wikis", "priority": 1 }
+            },
+            "apps": {
+                "Acme CMS": {
+                    "cats": [1],
+                    "website": "",
+                    "html": ["acme-app", "acme-([\\d.]+)\\;version:\\1\\;confidence:50"],
+                    "headers": { "X-Powered-By": "^Acme" },
+                    "implies": "PHP",
+                    "excludes": "Old Blog",
+                    "requiresCategory": 3
+                },
+                "New Wiki": { "cats": [3], "website": "", "html": "new-wiki" },
+                "PHP": { "cats": [1], "website": "" },
+                "MySQL": { "cats": [1], "website": "" }
+            }
+        }"#
+        .parse()
+        .unwrap();
+
+        let ruleset_diff = diff(&old, &new);
+        assert_eq!(ruleset_diff.added_apps, vec!["New Wiki"]);
+        assert_eq!(ruleset_diff.removed_apps, vec!["Old Blog"]);
+        assert_eq!(ruleset_diff.categories.len(), 3);
+
+        assert_eq!(ruleset_diff.changed_apps.len(), 1);
+        let acme = &ruleset_diff.changed_apps[0];
+        assert_eq!(
+            acme.categories,
+            Some((
+                vec![String::from("CMS"), String::from("Blogs")],
+                vec![String::from("CMS")]
+            ))
+        );
+        let patterns = acme
+            .patterns
+            .iter()
+            .map(|p| (p.field.as_str(), p.key.as_deref(), p.change.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            patterns,
+            vec![
+                ("headers", Some("X-Acme"), String::from("- ")),
+                ("headers", Some("X-Powered-By"), String::from("~ Acme -> ^Acme")),
+                ("html", None, String::from("+ acme-app")),
+                (
+                    "html",
+                    None,
+                    String::from(
+                        "~ acme-([\\d.]+)\\;version:\\1 -> acme-([\\d.]+)\\;version:\\1\\;confidence:50"
+                    )
+                ),
+                ("html", None, String::from("- acme-root")),
+            ]
+        );
+        assert_eq!(
+            acme.implies,
+            vec![
+                Change::Modified {
+                    old: String::from("PHP\\;confidence:50"),
+                    new: String::from("PHP")
+                },
+                Change::Removed(String::from("MySQL"))
+            ]
+        );
+        assert_eq!(acme.excludes, vec![Change::Added(String::from("Old Blog"))]);
+        assert_eq!(
+            acme.requires_category,
+            vec![Change::Added(String::from("3"))]
+        );
+
+        let text = ruleset_diff.to_string();
+        assert!(text.starts_with(
+            "+ New Wiki\n- Old Blog\n~ Acme CMS\n    categories: CMS, Blogs -> CMS\n"
+        ));
+        assert!(text.contains("    ~ headers[X-Powered-By]: Acme -> ^Acme\n"));
+        assert!(text.contains("    + requiresCategory: 3\n"));
+        assert!(text.contains("    ~ 1 CMS (priority 1) -> 1 CMS (priority 2)\n"));
+        assert!(text.ends_with("1 apps added, 1 removed, 1 changed, 3 categories changed"));
+
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_diff_dom() {
+        let apps_json = |dom: &str| {
+            let apps_json = r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": { "Acme CMS": { "cats": [1], "website": "", "dom": DOM } }
+            }"#;
+            apps_json
+                .replace("DOM", dom)
+                .parse::<AppsJsonData>()
+                .unwrap()
+        };
+        let old = apps_json(r#"{ "a": { "attributes": { "href": "x" } } }"#);
+        let new = apps_json(r#"{ "a": { "attributes": { "src": "x" } } }"#);
+
+        let ruleset_diff = diff(&old, &new);
+        assert_eq!(ruleset_diff.changed_apps.len(), 1);
+        assert_eq!(
+            ruleset_diff.changed_apps[0].patterns,
+            vec![
+                PatternChange {
+                    field: String::from("dom"),
+                    key: Some(String::from("a attributes.href")),
+                    change: Change::Removed(String::from("x")),
+                },
+                PatternChange {
+                    field: String::from("dom"),
+                    key: Some(String::from("a attributes.src")),
+                    change: Change::Added(String::from("x")),
+                },
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+}
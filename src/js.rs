@@ -0,0 +1,825 @@
+//! A static look at inline scripts, for the `js` rules: which globals they define (declare or
+//! assign), by property path (ex: `jQuery.fn.jquery`), and the literal value they are given
+//! when there is one. Nothing is run, so a global that is computed has no value, and one that
+//! is only defined at run time (by an external script, or through an alias) isn't found.
+
+use std::collections::HashMap;
+
+/// How deep object literals are followed, to bound the recursion on pathological scripts
+const MAX_OBJECT_DEPTH: usize = 32;
+
+/// A global defined by a script
+#[derive(Debug, PartialEq, Clone)]
+pub struct Global {
+    /// The value it is given, when it is a literal (a string, number or boolean), as a string
+    pub value: Option<String>,
+    /// The byte offset of the definition in the script
+    pub offset: usize,
+}
+
+/// Whether a `js` rule name is a property path (ex: `Modernizr._version`), the only names that
+/// can match a global
+pub fn is_property_path(path: &str) -> bool {
+    path.split('.').all(|name| {
+        let mut chars = name.chars();
+        chars.next().is_some_and(is_identifier_start) && chars.all(is_identifier_part)
+    })
+}
+
+/// Whether a script tag with this `type` holds a classic script, whose top-level declarations
+/// are globals: not json, templates and the like, nor a module
+pub(crate) fn is_javascript(script_type: Option<&str>) -> bool {
+    script_type.is_none_or(|mime| {
+        let mime = mime.to_lowercase();
+        mime.is_empty() || mime.contains("javascript")
+    })
+}
+
+/// The globals that a script defines, by property path: the top-level `var` and `function`
+/// declarations, the assignments at the top level (`Foo = ...`, `Foo.bar = ...`) or anywhere
+/// to an explicit global (`window.Foo = ...`), and the properties of the object literals they
+/// are given (`Foo = { version: "1.2" }` defines `Foo` and `Foo.version`).
+pub fn globals(script: &str) -> HashMap<String, Global> {
+    let mut scanner = Scanner {
+        tokens: tokenize(script),
+        pos: 0,
+        braces: vec![],
+        pending_class: false,
+        globals: HashMap::new(),
+    };
+    scanner.scan();
+    scanner.globals
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Kind {
+    Identifier,
+    String,
+    /// A template literal without substitutions
+    Template,
+    /// A template literal with `${...}` substitutions
+    Substitutions,
+    Number,
+    Regex,
+    Punctuator,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Token<'s> {
+    kind: Kind,
+    text: &'s str,
+    offset: usize,
+    /// Whether a line break separates it from the previous token, for the automatic
+    /// semicolon insertion
+    newline_before: bool,
+}
+
+impl Token<'_> {
+    fn is(&self, punctuator: &str) -> bool {
+        self.kind == Kind::Punctuator && self.text == punctuator
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.kind == Kind::Identifier && self.text == keyword
+    }
+}
+
+/// The longest first: the tokenizer takes the first one that the script continues with
+const PUNCTUATORS: &[&str] = &[
+    ">>>=", "...", "===", "!==", "**=", "<<=", ">>=", ">>>", "&&=", "||=", "??=", "=>", "==", "!=",
+    "<=", ">=", "&&", "||", "??", "?.", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=",
+    "**", "<<", ">>",
+];
+
+/// The keywords after which a `/` starts a regex rather than a division
+const KEYWORDS_BEFORE_EXPRESSION: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_part(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn tokenize(script: &str) -> Vec<Token<'_>> {
+    let bytes = script.as_bytes();
+    let mut tokens: Vec<Token<'_>> = vec![];
+    let mut newline_before = false;
+    let mut i = 0;
+
+    // the end of the line, or of the script
+    let line_end = |from: usize| script[from..].find('\n').map_or(script.len(), |n| from + n);
+    // past a backslash and the character it escapes
+    let escape_end =
+        |from: usize| from + 1 + script[from + 1..].chars().next().map_or(0, char::len_utf8);
+
+    while i < script.len() {
+        let rest = &script[i..];
+        let c = rest.chars().next().unwrap();
+        let start = i;
+
+        if c.is_whitespace() {
+            newline_before |= c == '\n' || c == '\r' || c == '\u{2028}' || c == '\u{2029}';
+            i += c.len_utf8();
+            continue;
+        }
+        // comments, including the html ones that old inline scripts are wrapped in
+        let line_start = newline_before || tokens.is_empty();
+        if rest.starts_with("//")
+            || rest.starts_with("<!--")
+            || (line_start && rest.starts_with("-->"))
+        {
+            i = line_end(i);
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("/*") {
+            let end = comment.find("*/").map_or(script.len(), |n| i + 2 + n + 2);
+            newline_before |= script[i..end].contains('\n');
+            i = end;
+            continue;
+        }
+
+        let kind = if is_identifier_start(c) {
+            i += rest
+                .find(|c: char| !is_identifier_part(c))
+                .unwrap_or(rest.len());
+            Kind::Identifier
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let mut end = 0;
+            while let Some(c) = rest[end..].chars().next() {
+                let exponent_sign = (c == '+' || c == '-')
+                    && rest[..end].ends_with(['e', 'E'])
+                    && !rest.starts_with("0x")
+                    && !rest.starts_with("0X");
+                if !(c.is_ascii_alphanumeric() || c == '.' || c == '_' || exponent_sign) {
+                    break;
+                }
+                end += 1;
+            }
+            i += end;
+            Kind::Number
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            while i < script.len() {
+                match bytes[i] {
+                    b'\\' => i = escape_end(i),
+                    b'\n' => break,
+                    b if b == c as u8 => {
+                        i += 1;
+                        break;
+                    }
+                    _ => i += 1,
+                }
+            }
+            Kind::String
+        } else if c == '`' {
+            let mut substitutions = false;
+            i += 1;
+            while i < script.len() {
+                match bytes[i] {
+                    b'\\' => i = escape_end(i),
+                    b'`' => {
+                        i += 1;
+                        break;
+                    }
+                    b'$' if bytes.get(i + 1) == Some(&b'{') => {
+                        // to the brace that closes the substitution (braces in its strings throw it off)
+                        substitutions = true;
+                        let mut depth = 0;
+                        while i < script.len() {
+                            match bytes[i] {
+                                b'{' => depth += 1,
+                                b'}' => {
+                                    depth -= 1;
+                                    if depth == 0 {
+                                        break;
+                                    }
+                                }
+                                _ => (),
+                            }
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    _ => i += 1,
+                }
+            }
+            if substitutions {
+                Kind::Substitutions
+            } else {
+                Kind::Template
+            }
+        } else if c == '/' && regex_allowed(tokens.last()) {
+            match regex_end(rest) {
+                Some(end) => {
+                    i += end;
+                    Kind::Regex
+                }
+                None => {
+                    i += 1;
+                    Kind::Punctuator
+                }
+            }
+        } else {
+            i += PUNCTUATORS
+                .iter()
+                .find(|p| rest.starts_with(*p))
+                .map_or(c.len_utf8(), |p| p.len());
+            Kind::Punctuator
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &script[start..i.min(script.len())],
+            offset: start,
+            newline_before,
+        });
+        newline_before = false;
+    }
+    tokens
+}
+
+/// Whether a `/` after this token starts a regex literal: not after what ends an operand
+fn regex_allowed(previous: Option<&Token<'_>>) -> bool {
+    match previous {
+        None => true,
+        Some(token) => match token.kind {
+            Kind::Identifier => KEYWORDS_BEFORE_EXPRESSION.contains(&token.text),
+            Kind::Punctuator => !matches!(token.text, ")" | "]" | "++" | "--"),
+            _ => false,
+        },
+    }
+}
+
+/// The length of the regex literal the text starts with, with its flags, if it ends on the line
+fn regex_end(text: &str) -> Option<usize> {
+    let mut in_class = false;
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' => return None,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => {
+                let flags = text[i + 1..]
+                    .find(|c: char| !is_identifier_part(c))
+                    .unwrap_or(text.len() - i - 1);
+                return Some(i + 1 + flags);
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The value of a string literal (or of a template literal without substitutions)
+fn unquote(literal: &str) -> String {
+    let quote = literal.chars().next().unwrap_or('"');
+    let inner = &literal[quote.len_utf8().min(literal.len())..];
+    let inner = inner.strip_suffix(quote).unwrap_or(inner);
+    let mut value = String::new();
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some(escaped) => escaped,
+            None => break,
+        };
+        let hex = |chars: &mut std::iter::Peekable<std::str::Chars<'_>>, len: usize| {
+            let digits = chars.by_ref().take(len).collect::<String>();
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+        };
+        match escaped {
+            'n' => value.push('\n'),
+            't' => value.push('\t'),
+            'r' => value.push('\r'),
+            'b' => value.push('\u{8}'),
+            'f' => value.push('\u{c}'),
+            'v' => value.push('\u{b}'),
+            '0' => value.push('\0'),
+            'x' => value.extend(hex(&mut chars, 2)),
+            'u' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let digits = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                value.extend(
+                    u32::from_str_radix(&digits, 16)
+                        .ok()
+                        .and_then(char::from_u32),
+                );
+            }
+            'u' => value.extend(hex(&mut chars, 4)),
+            // a line continuation
+            '\n' => (),
+            '\r' => {
+                chars.next_if_eq(&'\n');
+            }
+            _ => value.push(escaped),
+        }
+    }
+    value
+}
+
+/// What a `{` opens
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Brace {
+    /// The body of a function, method or arrow function: what it declares is local
+    Function,
+    /// The body of a class, whose braces are those of its methods
+    Class,
+    /// A block (of an `if`, a loop, ...) or an object literal that isn't followed
+    Block,
+}
+
+struct Scanner<'s> {
+    tokens: Vec<Token<'s>>,
+    pos: usize,
+    /// The braces the scanner is in
+    braces: Vec<Brace>,
+    /// Whether a `class` keyword was seen, and the next `{` opens its body
+    pending_class: bool,
+    globals: HashMap<String, Global>,
+}
+
+impl<'s> Scanner<'s> {
+    fn token(&self, pos: usize) -> Option<&Token<'s>> {
+        self.tokens.get(pos)
+    }
+
+    fn is_global_scope(&self) -> bool {
+        !self.braces.contains(&Brace::Function)
+    }
+
+    fn define(&mut self, path: String, value: Option<String>, offset: usize) {
+        self.globals.insert(path, Global { value, offset });
+    }
+
+    fn scan(&mut self) {
+        while let Some(token) = self.token(self.pos).copied() {
+            match token.kind {
+                Kind::Punctuator if token.text == "{" => {
+                    let brace = self.brace_kind();
+                    self.braces.push(brace);
+                    self.pos += 1;
+                }
+                Kind::Punctuator if token.text == "}" => {
+                    self.braces.pop();
+                    self.pos += 1;
+                }
+                Kind::Identifier if token.text == "class" => {
+                    self.pending_class = true;
+                    self.pos += 1;
+                }
+                Kind::Identifier if token.text == "var" && self.is_global_scope() => {
+                    self.declarations(true);
+                }
+                // lexical declarations aren't properties of the global object
+                Kind::Identifier if token.text == "let" || token.text == "const" => {
+                    self.declarations(false);
+                }
+                Kind::Identifier if token.text == "function" => {
+                    match self.token(self.pos + 1) {
+                        Some(name) if name.kind == Kind::Identifier && self.is_global_scope() => {
+                            let at_statement = self.pos == 0 || self.at_statement_start(self.pos);
+                            if at_statement {
+                                self.define(String::from(name.text), None, name.offset);
+                            }
+                        }
+                        _ => (),
+                    }
+                    self.pos += 1;
+                }
+                Kind::Identifier if !self.follows_member_access(self.pos) => {
+                    if !self.assignment() {
+                        self.pos += 1;
+                    }
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// What the `{` at the current position opens, from the tokens before it
+    fn brace_kind(&mut self) -> Brace {
+        if std::mem::take(&mut self.pending_class) {
+            return Brace::Class;
+        }
+        let previous = match self.pos.checked_sub(1).and_then(|pos| self.token(pos)) {
+            Some(previous) => *previous,
+            None => return Brace::Block,
+        };
+        if previous.is("=>") || self.braces.last() == Some(&Brace::Class) {
+            return Brace::Function;
+        }
+        if !previous.is(")") {
+            return Brace::Block;
+        }
+        // `if (...) {` opens a block, `function f(...) {` and `method(...) {` a function
+        let mut depth = 0;
+        for pos in (0..self.pos).rev() {
+            let token = self.tokens[pos];
+            if token.is(")") {
+                depth += 1;
+            } else if token.is("(") {
+                depth -= 1;
+                if depth == 0 {
+                    let keyword = pos.checked_sub(1).map(|pos| self.tokens[pos]);
+                    let is_block = keyword.is_some_and(|keyword| {
+                        ["if", "for", "while", "switch", "catch", "with"]
+                            .iter()
+                            .any(|k| keyword.is_keyword(k))
+                    });
+                    return if is_block {
+                        Brace::Block
+                    } else {
+                        Brace::Function
+                    };
+                }
+            }
+        }
+        Brace::Block
+    }
+
+    /// Whether the token at `pos` follows a `.` or `?.`, as in the `b` of `a.b`
+    fn follows_member_access(&self, pos: usize) -> bool {
+        pos.checked_sub(1)
+            .and_then(|pos| self.token(pos))
+            .is_some_and(|previous| previous.is(".") || previous.is("?."))
+    }
+
+    /// Whether the token at `pos` starts a statement (loosely: `function` after an operator is
+    /// a function expression)
+    fn at_statement_start(&self, pos: usize) -> bool {
+        match pos.checked_sub(1).and_then(|pos| self.token(pos)) {
+            None => true,
+            Some(previous) => {
+                previous.is(";")
+                    || previous.is("{")
+                    || previous.is("}")
+                    || self.tokens[pos].newline_before
+            }
+        }
+    }
+
+    /// `var a = ..., b = ...;` at the top level, which defines `a` and `b`, or a `let` or
+    /// `const` declaration, which is only followed
+    fn declarations(&mut self, define: bool) {
+        self.pos += 1;
+        while let Some(name) = self.token(self.pos).copied() {
+            if name.kind != Kind::Identifier || !self.token(self.pos + 1).is_some_and(|t| t.is("="))
+            {
+                return;
+            }
+            self.pos += 2;
+            let path = String::from(name.text);
+            let paths = if define { vec![path.clone()] } else { vec![] };
+            let value = self.value(&paths, 0);
+            if define {
+                self.define(path, value.clone().flatten(), name.offset);
+            }
+            // the scan goes on in the value, which isn't followed
+            if value.is_none() {
+                return;
+            }
+            if !self.token(self.pos).is_some_and(|t| t.is(",")) {
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// The property path of the assignment target at the current position, if there is one
+    /// (as `a.b["c"]`), with the position of the `=` after it
+    fn target(&self, pos: usize) -> Option<(Vec<String>, usize)> {
+        let mut path = vec![String::from(self.token(pos)?.text)];
+        let mut pos = pos + 1;
+        loop {
+            let token = self.token(pos)?;
+            if token.is(".") {
+                let name = self.token(pos + 1).filter(|t| t.kind == Kind::Identifier)?;
+                path.push(String::from(name.text));
+                pos += 2;
+            } else if token.is("[") {
+                let key = self.token(pos + 1).filter(|t| t.kind == Kind::String)?;
+                if !self.token(pos + 2)?.is("]") {
+                    return None;
+                }
+                // a key that isn't a name can't be in a `js` rule
+                let key = unquote(key.text);
+                if !is_property_path(&key) || key.contains('.') {
+                    return None;
+                }
+                path.push(key);
+                pos += 3;
+            } else if token.is("=") {
+                return Some((path, pos));
+            } else {
+                return None;
+            }
+        }
+    }
+
+    /// The global path assigned at the current position: the assignments to `window.*`,
+    /// `self.*` and `globalThis.*` anywhere, and the others at the top level only
+    fn global_path(&self, path: &[String]) -> Option<String> {
+        let explicit = ["window", "self", "globalThis"].contains(&path[0].as_str());
+        let path = if explicit || (path[0] == "this" && self.is_global_scope()) {
+            &path[1..]
+        } else if self.is_global_scope() {
+            path
+        } else {
+            return None;
+        };
+        if path.is_empty() {
+            None
+        } else {
+            Some(path.join("."))
+        }
+    }
+
+    /// Handles the assignment at the current position, if there is one, returning whether it
+    /// did. Chained assignments (`a = b = ...`) all get the value.
+    fn assignment(&mut self) -> bool {
+        let mut paths = vec![];
+        let mut offsets = vec![];
+        let mut pos = self.pos;
+        while let Some((target, eq)) = self
+            .token(pos)
+            .filter(|t| t.kind == Kind::Identifier)
+            .and_then(|_| self.target(pos))
+        {
+            if let Some(path) = self.global_path(&target) {
+                paths.push(path);
+                offsets.push(self.tokens[pos].offset);
+            }
+            pos = eq + 1;
+        }
+        if pos == self.pos {
+            return false;
+        }
+        self.pos = pos;
+        let value = self.value(&paths, 0).flatten();
+        for (path, offset) in paths.into_iter().zip(offsets) {
+            self.define(path, value.clone(), offset);
+        }
+        true
+    }
+
+    /// Whether the expression ends before the token at `pos`
+    fn ends_at(&self, pos: usize) -> bool {
+        match self.token(pos) {
+            None => true,
+            Some(token) => {
+                [",", ";", ")", "]", "}"].iter().any(|p| token.is(p))
+                    || (token.newline_before && token.kind != Kind::Punctuator)
+            }
+        }
+    }
+
+    /// Follows the value at the current position, if it is a literal, or an object literal
+    /// whose properties are defined under each of the paths. Returns `Some(value)` when it was
+    /// followed (and the position is after it), and `None` when it wasn't (the position
+    /// hasn't moved).
+    fn value(&mut self, paths: &[String], depth: usize) -> Option<Option<String>> {
+        let token = *self.token(self.pos)?;
+        if token.is("{") && depth < MAX_OBJECT_DEPTH {
+            self.object(paths, depth);
+            return Some(None);
+        }
+
+        let mut literal = String::new();
+        let mut pos = self.pos;
+        loop {
+            let token = self.token(pos)?;
+            match token.kind {
+                Kind::String | Kind::Template => literal.push_str(&unquote(token.text)),
+                Kind::Number => literal.push_str(token.text),
+                Kind::Identifier if ["true", "false"].contains(&token.text) && pos == self.pos => {
+                    literal.push_str(token.text)
+                }
+                _ => return None,
+            }
+            pos += 1;
+            // "1." + "2", as a version is sometimes built
+            if self.token(pos).is_some_and(|t| t.is("+")) && !self.ends_at(pos + 1) {
+                pos += 1;
+                continue;
+            }
+            if !self.ends_at(pos) {
+                return None;
+            }
+            self.pos = pos;
+            return Some(Some(literal));
+        }
+    }
+
+    /// Follows an object literal, defining its properties under each of the paths
+    fn object(&mut self, paths: &[String], depth: usize) {
+        self.pos += 1;
+        while let Some(token) = self.token(self.pos).copied() {
+            if token.is("}") {
+                self.pos += 1;
+                return;
+            }
+            // not an object literal after all
+            if token.is(")") || token.is("]") {
+                return;
+            }
+            if token.is(",") {
+                self.pos += 1;
+                continue;
+            }
+            let key = match token.kind {
+                Kind::Identifier | Kind::Number => Some(String::from(token.text)),
+                Kind::String => Some(unquote(token.text)),
+                _ => None,
+            };
+            let next = self.token(self.pos + 1).copied();
+            match (key, next) {
+                (Some(key), Some(next)) if next.is(":") => {
+                    self.pos += 2;
+                    let child_paths = paths
+                        .iter()
+                        .map(|path| format!("{}.{}", path, key))
+                        .collect::<Vec<_>>();
+                    let value = match self.value(&child_paths, depth + 1) {
+                        Some(value) => value,
+                        None => {
+                            self.skip_value();
+                            None
+                        }
+                    };
+                    for path in child_paths {
+                        self.define(path, value.clone(), token.offset);
+                    }
+                }
+                // shorthand properties (`{ a, b }`) and methods (`{ init() {} }`)
+                (Some(key), Some(next)) if next.is(",") || next.is("}") || next.is("(") => {
+                    for path in paths {
+                        self.define(format!("{}.{}", path, key), None, token.offset);
+                    }
+                    self.pos += 1;
+                    self.skip_value();
+                }
+                // getters, setters, spreads, computed keys
+                _ => self.skip_value(),
+            }
+        }
+    }
+
+    /// Skips to the `,` or `}` that ends the current property of an object literal
+    fn skip_value(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.token(self.pos) {
+            if token.is("(") || token.is("[") || token.is("{") {
+                depth += 1;
+            } else if token.is(")") || token.is("]") || token.is("}") {
+                if depth == 0 {
+                    return;
+                }
+                depth -= 1;
+            } else if token.is(",") && depth == 0 {
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(globals: &HashMap<String, Global>, path: &str) -> Option<Option<String>> {
+        globals.get(path).map(|global| global.value.clone())
+    }
+
+    #[test]
+    fn test_globals() {
+        let globals = globals(
+            r#"
+            <!--
+            var Modernizr = { _version: "3.6.0", mq: function(q) { var local = 1; return q } };
+            window.wp_username = 'admin', window["Shopify"] = {};
+            Shopify.theme = { name: "Debut", "id": 42 };
+            jQuery.fn = jQuery.prototype = { jquery: "1." + "12.4", constructor: jQuery };
+            function Drupal() {}
+            let notOnWindow = "x";
+            (function () {
+                var hidden = "1.0";
+                window.Vue = { version: `2.6.14` };
+                self.inner = computeIt();
+                if (ready) { Flag.on = true }
+            })();
+            var computed = makeIt(), after = "no";
+            if (x) { var inBlock = 'yes' }
+            var re = /\/var fake = "x"/g, count = 2
+            str = "a // not a comment";
+            // var commented = "x";
+            /* window.commented = "x"; */
+            -->
+            "#,
+        );
+
+        assert_eq!(value(&globals, "Modernizr"), Some(None));
+        assert_eq!(
+            value(&globals, "Modernizr._version"),
+            Some(Some(String::from("3.6.0")))
+        );
+        assert_eq!(value(&globals, "Modernizr.mq"), Some(None));
+        assert_eq!(value(&globals, "local"), None);
+        assert_eq!(
+            value(&globals, "wp_username"),
+            Some(Some(String::from("admin")))
+        );
+        assert_eq!(value(&globals, "Shopify"), Some(None));
+        assert_eq!(
+            value(&globals, "Shopify.theme.name"),
+            Some(Some(String::from("Debut")))
+        );
+        assert_eq!(
+            value(&globals, "Shopify.theme.id"),
+            Some(Some(String::from("42")))
+        );
+        assert_eq!(
+            value(&globals, "jQuery.fn.jquery"),
+            Some(Some(String::from("1.12.4")))
+        );
+        assert_eq!(
+            value(&globals, "jQuery.prototype.jquery"),
+            Some(Some(String::from("1.12.4")))
+        );
+        assert_eq!(value(&globals, "jQuery.fn.constructor"), Some(None));
+        assert_eq!(value(&globals, "Drupal"), Some(None));
+        assert_eq!(value(&globals, "notOnWindow"), None);
+        assert_eq!(value(&globals, "hidden"), None);
+        assert_eq!(
+            value(&globals, "Vue.version"),
+            Some(Some(String::from("2.6.14")))
+        );
+        assert_eq!(value(&globals, "inner"), Some(None));
+        assert_eq!(value(&globals, "Flag.on"), None);
+        assert_eq!(value(&globals, "computed"), Some(None));
+        assert_eq!(value(&globals, "after"), Some(Some(String::from("no"))));
+        assert_eq!(value(&globals, "inBlock"), Some(Some(String::from("yes"))));
+        assert_eq!(value(&globals, "fake"), None);
+        assert_eq!(value(&globals, "count"), Some(Some(String::from("2"))));
+        assert_eq!(
+            value(&globals, "str"),
+            Some(Some(String::from("a // not a comment")))
+        );
+        assert_eq!(value(&globals, "commented"), None);
+    }
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote(r#""a\"b""#), "a\"b");
+        assert_eq!(unquote(r"'\x41B\u{43}\n'"), "ABC\n");
+        assert_eq!(unquote("'"), "");
+    }
+
+    #[test]
+    fn test_is_property_path() {
+        assert!(is_property_path("jQuery.fn.jquery"));
+        assert!(is_property_path("$.fancybox.version"));
+        assert!(is_property_path("_gs"));
+        assert!(!is_property_path("UC_IMAGE_SERVICE|ITEM_INFO_SERVICE"));
+        assert!(!is_property_path("ScrollReveal().version"));
+        assert!(!is_property_path("a..b"));
+    }
+
+    #[test]
+    fn test_is_javascript() {
+        assert!(is_javascript(None));
+        assert!(is_javascript(Some("")));
+        assert!(is_javascript(Some("text/JavaScript")));
+        assert!(!is_javascript(Some("module")));
+        assert!(!is_javascript(Some("application/ld+json")));
+        assert!(!is_javascript(Some("text/x-template")));
+    }
+}
@@ -0,0 +1,433 @@
+//! Runs a page's scripts in an embedded QuickJS, for the `js` rules: the globals are then read
+//! from the global object, as the JS library does in a browser, rather than guessed from the
+//! scripts' text (see `js::globals`). The scripts run in a bare runtime, with a stub of `window`
+//! and `document` that does nothing: there is no network, no file system and no timers, and
+//! every page gets a fresh runtime, within time and memory limits.
+
+use crate::js::{self, Global};
+use crate::wapp::RawData;
+use crate::WappError;
+use rquickjs::context::EvalOptions;
+use rquickjs::{Context, Ctx, Object, Runtime, Value};
+use scraper::{Html, Selector};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// A `window` and `document` that are just enough for most scripts to run to the end. The
+/// function is called with the location of the page and its cookies.
+const PRELUDE: &str = r#"
+(function (location, cookie) {
+    var g = globalThis;
+    var noop = function () {};
+    var none = function () { return null; };
+    var empty = function () { return []; };
+    var element = function (tagName) {
+        return {
+            tagName: String(tagName).toUpperCase(),
+            nodeName: String(tagName).toUpperCase(),
+            style: {},
+            dataset: {},
+            attributes: [],
+            childNodes: [],
+            children: [],
+            classList: { add: noop, remove: noop, toggle: noop, contains: function () { return false; } },
+            setAttribute: noop,
+            getAttribute: none,
+            removeAttribute: noop,
+            hasAttribute: function () { return false; },
+            appendChild: function (child) { return child; },
+            insertBefore: function (child) { return child; },
+            removeChild: function (child) { return child; },
+            addEventListener: noop,
+            removeEventListener: noop,
+            querySelector: none,
+            querySelectorAll: empty,
+            getElementsByTagName: empty,
+            getElementsByClassName: empty
+        };
+    };
+    var storage = { getItem: none, setItem: noop, removeItem: noop, clear: noop, key: none, length: 0 };
+
+    g.window = g.self = g.top = g.parent = g.frames = g;
+    g.location = location;
+    g.document = {
+        documentElement: element('html'),
+        head: element('head'),
+        body: element('body'),
+        readyState: 'complete',
+        cookie: cookie,
+        referrer: '',
+        title: '',
+        URL: location.href,
+        location: location,
+        currentScript: null,
+        createElement: element,
+        createElementNS: function (namespace, tagName) { return element(tagName); },
+        createTextNode: function () { return element('#text'); },
+        createDocumentFragment: function () { return element('#document-fragment'); },
+        getElementById: none,
+        querySelector: none,
+        querySelectorAll: empty,
+        getElementsByTagName: empty,
+        getElementsByClassName: empty,
+        getElementsByName: empty,
+        addEventListener: noop,
+        removeEventListener: noop,
+        write: noop,
+        writeln: noop
+    };
+    g.navigator = {
+        userAgent: 'Mozilla/5.0 (compatible; wappalyzer)',
+        language: 'en-US',
+        languages: ['en-US'],
+        platform: '',
+        cookieEnabled: true,
+        plugins: [],
+        sendBeacon: function () { return false; }
+    };
+    g.screen = { width: 1920, height: 1080 };
+    g.innerWidth = 1920;
+    g.innerHeight = 1080;
+    g.devicePixelRatio = 1;
+    g.localStorage = g.sessionStorage = storage;
+    g.console = { log: noop, info: noop, warn: noop, error: noop, debug: noop };
+    // nothing is ever called back, or sent
+    g.setTimeout = g.setInterval = g.requestAnimationFrame = g.requestIdleCallback = function () { return 0; };
+    g.clearTimeout = g.clearInterval = g.cancelAnimationFrame = noop;
+    g.addEventListener = g.removeEventListener = noop;
+    g.dispatchEvent = function () { return true; };
+    g.fetch = function () { return new Promise(noop); };
+    g.XMLHttpRequest = function () {};
+    g.XMLHttpRequest.prototype = { open: noop, send: noop, setRequestHeader: noop, abort: noop };
+    g.Image = function () { return element('img'); };
+    g.Event = g.CustomEvent = noop;
+    g.MutationObserver = g.IntersectionObserver = g.ResizeObserver = function () {
+        return { observe: noop, unobserve: noop, disconnect: noop };
+    };
+    g.getComputedStyle = function () { return { getPropertyValue: function () { return ''; } }; };
+    g.matchMedia = function () {
+        return { matches: false, addListener: noop, removeListener: noop, addEventListener: noop };
+    };
+})
+"#;
+
+/// How long and how much memory the scripts of a page get
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLimits {
+    /// For all of the scripts of a page, and reading the globals
+    pub time: Duration,
+    /// The memory of the runtime, in bytes
+    pub memory: usize,
+    /// The stack of the runtime, in bytes
+    pub stack: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> ScriptLimits {
+        ScriptLimits {
+            time: Duration::from_millis(500),
+            memory: 64 << 20,
+            stack: 1 << 20,
+        }
+    }
+}
+
+/// The scripts of the page that are run, in the order of the page: the inline ones, and the
+/// external ones from the same origin as the page whose contents are in `external_scripts`
+pub fn page_scripts(raw_data: &RawData) -> Vec<String> {
+    let origin = Url::parse(&raw_data.final_url).map(|url| url.origin()).ok();
+    let same_origin = |src: &str| {
+        let src_origin = Url::parse(src).map(|url| url.origin()).ok();
+        src_origin.is_some() && src_origin == origin
+    };
+
+    // the srcs are those of the script tags with one, in the same order
+    let mut srcs = raw_data.script_srcs.iter();
+    let mut scripts = vec![];
+    for tag in raw_data.script_tags.iter() {
+        let fragment = Html::parse_fragment(tag);
+        let script = match fragment.select(&Selector::parse("script").unwrap()).next() {
+            Some(script) => script,
+            None => continue,
+        };
+        let is_javascript = js::is_javascript(script.value().attr("type"));
+        if script.value().attr("src").is_some() {
+            let src = srcs.next();
+            if let Some(content) = src
+                .filter(|src| is_javascript && same_origin(src))
+                .and_then(|src| raw_data.external_scripts.get(src))
+            {
+                scripts.push(content.clone());
+            }
+        } else if is_javascript {
+            scripts.push(script.text().collect());
+        }
+    }
+    scripts
+}
+
+/// Runs the scripts of the page, then reads each of the property paths from the global object.
+/// A script that throws is skipped, as in a browser; when the time is up, the scripts left
+/// aren't run. Only the paths with a value other than `undefined` and `null` are returned.
+pub fn run_scripts(
+    raw_data: &RawData,
+    paths: &[String],
+    limits: &ScriptLimits,
+) -> Result<HashMap<String, Global>, WappError> {
+    let engine_error = |err: rquickjs::Error| {
+        WappError::Analyze(format!(
+            "Error running the scripts of {}: {}",
+            raw_data.url, err
+        ))
+    };
+
+    let runtime = Runtime::new().map_err(engine_error)?;
+    runtime.set_memory_limit(limits.memory);
+    runtime.set_max_stack_size(limits.stack);
+    let deadline = Instant::now() + limits.time;
+    runtime.set_interrupt_handler(Some(Box::new(move || Instant::now() >= deadline)));
+    let context = Context::full(&runtime).map_err(engine_error)?;
+
+    let scripts = page_scripts(raw_data);
+    context.with(|ctx| -> Result<(), WappError> {
+        let prelude: rquickjs::Function = ctx.eval(PRELUDE).map_err(engine_error)?;
+        let location = location(&raw_data.final_url);
+        let cookie = raw_data
+            .cookies
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let location: Value = ctx.json_parse(location.to_string()).map_err(engine_error)?;
+        prelude
+            .call::<_, ()>((location, cookie))
+            .map_err(engine_error)?;
+
+        for script in scripts {
+            if Instant::now() >= deadline {
+                break;
+            }
+            let mut options = EvalOptions::default();
+            // page scripts are sloppy unless they say otherwise
+            options.strict = false;
+            if ctx.eval_with_options::<(), _>(script, options).is_err() {
+                // clear the exception
+                ctx.catch();
+            }
+        }
+        Ok(())
+    })?;
+
+    // the jobs that the scripts queued (promise callbacks), within the same time; one that
+    // throws is skipped, as a script is
+    while Instant::now() < deadline && runtime.is_job_pending() {
+        let _ = runtime.execute_pending_job();
+    }
+
+    Ok(context.with(|ctx| {
+        paths
+            .iter()
+            .filter_map(|path| Some((path.clone(), read_global(&ctx, path)?)))
+            .collect()
+    }))
+}
+
+/// The value at the property path, from the global object
+fn read_global<'js>(ctx: &Ctx<'js>, path: &str) -> Option<Global> {
+    let mut value: Value<'js> = ctx.globals().into_value();
+    for name in path.split('.') {
+        // as in the JS library, only objects (and functions) have properties to follow
+        let object: Object<'js> = value.into_object()?;
+        value = object.get(name).ok()?;
+        if value.is_undefined() || value.is_null() {
+            return None;
+        }
+    }
+
+    let literal = if let Some(string) = value.as_string() {
+        Some(string.to_string().ok()?)
+    } else if let Some(int) = value.as_int() {
+        Some(int.to_string())
+    } else if let Some(float) = value.as_float() {
+        Some(float.to_string())
+    } else {
+        value.as_bool().map(|b| b.to_string())
+    };
+    Some(Global {
+        value: literal,
+        offset: 0,
+    })
+}
+
+/// `window.location`, for a page URL
+fn location(page_url: &str) -> serde_json::Value {
+    let url = match Url::parse(page_url) {
+        Ok(url) => url,
+        Err(_) => return json!({ "href": page_url }),
+    };
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => String::from(url.host_str().unwrap_or_default()),
+    };
+    json!({
+        "href": url.as_str(),
+        "origin": url.origin().ascii_serialization(),
+        "protocol": format!("{}:", url.scheme()),
+        "host": host,
+        "hostname": url.host_str().unwrap_or_default(),
+        "port": url.port().map(|port| port.to_string()).unwrap_or_default(),
+        "pathname": url.path(),
+        "search": url.query().map(|query| format!("?{}", query)).unwrap_or_default(),
+        "hash": url.fragment().map(|fragment| format!("#{}", fragment)).unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wapp::Engine;
+    use std::sync::Arc;
+
+    fn raw_data(html: &str, external_scripts: &[(&str, &str)]) -> RawData {
+        let page_url = Url::parse("https://example.com/").unwrap();
+        let headers = reqwest::header::HeaderMap::new();
+        let mut raw_data = RawData::new("", &page_url, headers, vec![], String::from(html));
+        raw_data.external_scripts = external_scripts
+            .iter()
+            .map(|(src, content)| (String::from(*src), String::from(*content)))
+            .collect();
+        raw_data
+    }
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| String::from(*path)).collect()
+    }
+
+    fn value(globals: &HashMap<String, Global>, path: &str) -> Option<Option<String>> {
+        globals.get(path).map(|global| global.value.clone())
+    }
+
+    #[test]
+    fn test_run_scripts() {
+        let raw_data = raw_data(
+            r#"
+            <script>
+                (function (w) {
+                    var version = [3, 5, 1].join('.');
+                    w.jQuery = w.$ = function () {};
+                    w.jQuery.fn = { jquery: version };
+                    document.createElement('div').setAttribute('id', 'x');
+                })(window);
+            </script>
+            <script>throw new Error('broken');</script>
+            <script src="/app.js"></script>
+            <script src="https://cdn.example.net/lib.js"></script>
+            <script type="application/ld+json">{"NotRun": true}</script>
+            <script>var Late = location.hostname + ':' + typeof App;</script>
+            "#,
+            &[
+                ("https://example.com/app.js", "var App = { version: 2 };"),
+                ("https://cdn.example.net/lib.js", "var Cdn = true;"),
+            ],
+        );
+        let globals = run_scripts(
+            &raw_data,
+            &paths(&[
+                "jQuery.fn.jquery",
+                "$",
+                "App.version",
+                "Cdn",
+                "NotRun",
+                "Late",
+                "jQuery.fn.jquery.length",
+                "Missing.path",
+            ]),
+            &ScriptLimits::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            value(&globals, "jQuery.fn.jquery"),
+            Some(Some(String::from("3.5.1")))
+        );
+        assert_eq!(value(&globals, "$"), Some(None));
+        // same origin only
+        assert_eq!(
+            value(&globals, "App.version"),
+            Some(Some(String::from("2")))
+        );
+        assert_eq!(value(&globals, "Cdn"), None);
+        assert_eq!(value(&globals, "NotRun"), None);
+        assert_eq!(
+            value(&globals, "Late"),
+            Some(Some(String::from("example.com:object")))
+        );
+        // strings have no properties to follow
+        assert_eq!(value(&globals, "jQuery.fn.jquery.length"), None);
+        assert_eq!(value(&globals, "Missing.path"), None);
+    }
+
+    #[test]
+    fn test_engine_globals() {
+        let engine = r#"{
+            "categories": { "1": { "name": "CMS", "priority": 1 } },
+            "apps": { "Acme": { "cats": [1], "website": "", "js": { "Acme": "" } } }
+        }"#
+        .parse::<Engine>()
+        .unwrap()
+        .with_js_engine(ScriptLimits::default());
+        let raw_data = Arc::new(raw_data(
+            "<script>var Acme = [1, 2].join('.');</script>",
+            &[],
+        ));
+        let offset = raw_data.js_globals.get("Acme").unwrap().offset;
+        assert!(offset > 0);
+
+        // the value is the engine's, the offset that of the text
+        let prepared = engine.prepare(raw_data);
+        let global = prepared.js_globals.get("Acme").unwrap();
+        assert_eq!(global.value.as_deref(), Some("1.2"));
+        assert_eq!(global.offset, offset);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = ScriptLimits {
+            time: Duration::from_millis(100),
+            ..ScriptLimits::default()
+        };
+
+        let start = Instant::now();
+        let globals = run_scripts(
+            &raw_data(
+                "<script>var Before = 1;</script><script>while (true) {}</script><script>var After = 1;</script>",
+                &[],
+            ),
+            &paths(&["Before", "After"]),
+            &limits,
+        )
+        .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(value(&globals, "Before"), Some(Some(String::from("1"))));
+        assert_eq!(value(&globals, "After"), None);
+
+        let limits = ScriptLimits {
+            memory: 16 << 20,
+            time: Duration::from_secs(5),
+            ..ScriptLimits::default()
+        };
+        let globals = run_scripts(
+            &raw_data(
+                "<script>var a = []; while (true) { a.push(new Array(1000).fill('x')) }</script><script>var After = 1;</script>",
+                &[],
+            ),
+            &paths(&["After"]),
+            &limits,
+        )
+        .unwrap();
+        // the runtime is still usable after running out of memory
+        assert_eq!(value(&globals, "After"), Some(Some(String::from("1"))));
+    }
+}
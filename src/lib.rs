@@ -1,20 +1,32 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod corpus;
+pub mod cpe;
+pub mod diff;
+pub mod js;
+#[cfg(feature = "js-engine")]
+pub mod js_engine;
+pub mod profile;
+pub mod validate;
 pub mod wapp;
 
-use scraper::{Html, Selector};
+use futures::future::{join_all, BoxFuture, FutureExt, Shared};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use url::Url;
-use wapp::{RawData, Tech};
+use wapp::{Engine, Exclusion, RawData, Tech};
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Analysis {
     pub url: String,
     pub result: Result<Vec<Tech>, String>,
+    /// Detections dropped because of the `excludes` of another detected tech
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded: Vec<Exclusion>,
 }
 
 /// Possible Errors in the domain_info lib
@@ -22,6 +34,8 @@ pub struct Analysis {
 pub enum WappError {
     Fetch(String),
     Analyze(String),
+    /// Loading or compiling a ruleset (apps.json)
+    Ruleset(String),
     Other(String),
 }
 
@@ -33,12 +47,15 @@ impl fmt::Display for WappError {
             match self {
                 WappError::Fetch(err) => format!("Fetch/{}", err),
                 WappError::Analyze(err) => format!("Analyze/{}", err),
+                WappError::Ruleset(err) => format!("Ruleset/{}", err),
                 WappError::Other(err) => format!("Other/{}", err),
             }
         )
     }
 }
 
+impl std::error::Error for WappError {}
+
 impl std::convert::From<std::io::Error> for WappError {
     fn from(err: std::io::Error) -> Self {
         WappError::Other(err.to_string())
@@ -62,25 +79,341 @@ impl From<std::str::Utf8Error> for WappError {
     }
 }
 
+/// Options to tune a scan
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// Technologies detected with a lower confidence than this are left out of the `Analysis`
+    pub min_confidence: u32,
+    /// Keep the rules that matched (and what they matched) on every `Tech`
+    pub evidence: bool,
+    /// Also fetch the pages' external scripts and stylesheets, within these limits, for the
+    /// rules on their contents (`scripts`, `css`, `js`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assets: Option<AssetLimits>,
+}
+
+/// What to fetch of the external scripts and stylesheets of a page: those of its own site
+/// (see `same_site`) and of the allowed hosts, the scripts first
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AssetLimits {
+    /// At most this many scripts and stylesheets per page
+    pub max_count: usize,
+    /// The bytes read from each, past which it is cut: the banner comments that give away a
+    /// library and its version are at the top
+    pub max_size: usize,
+    /// Third-party hosts to fetch from too, each with its subdomains
+    pub hosts: Vec<String>,
+    /// How long to wait for each, from the request to the last byte read
+    pub timeout: Duration,
+}
+
+impl Default for AssetLimits {
+    /// 20 assets of up to 256KB, from the page's site and a few public CDNs, within 10 seconds
+    fn default() -> Self {
+        AssetLimits {
+            max_count: 20,
+            max_size: 256 << 10,
+            hosts: [
+                "ajax.googleapis.com",
+                "cdn.jsdelivr.net",
+                "cdnjs.cloudflare.com",
+                "code.jquery.com",
+                "unpkg.com",
+            ]
+            .iter()
+            .map(|host| String::from(*host))
+            .collect(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
 pub async fn scan(url: Url) -> Analysis {
+    scan_with_options(url, &ScanOptions::default()).await
+}
+
+pub async fn scan_with_options(url: Url, options: &ScanOptions) -> Analysis {
+    scan_with(&Engine::embedded(), url, options).await
+}
+
+/// Scans the page with the apps of the given engine, rather than the embedded apps.json
+pub async fn scan_with(engine: &Engine, url: Url, options: &ScanOptions) -> Analysis {
+    scan_page(engine, url, options, &AssetCache::new(options))
+        .await
+        .0
+}
+
+/// Scans the pages together, fetching the external scripts and stylesheets that they share
+/// once (see `ScanOptions::assets`)
+pub async fn scan_batch(engine: &Engine, urls: Vec<Url>, options: &ScanOptions) -> Vec<Analysis> {
+    let assets = AssetCache::new(options);
+    let scans = urls
+        .into_iter()
+        .map(|url| scan_page(engine, url, options, &assets));
+    join_all(scans)
+        .await
+        .into_iter()
+        .map(|(analysis, _)| analysis)
+        .collect()
+}
+
+/// How `scan_site` crawls a site from its landing page
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CrawlOptions {
+    /// How many links away from the landing page to go, 0 only scanning the landing page
+    pub max_depth: usize,
+    /// At most this many pages are scanned, the landing page included
+    pub max_pages: usize,
+    pub scope: CrawlScope,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        CrawlOptions {
+            max_depth: 2,
+            max_pages: 20,
+            scope: CrawlScope::SameOrigin,
+        }
+    }
+}
+
+/// The links that `scan_site` follows
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrawlScope {
+    /// To the scheme, host and port of the landing page
+    SameOrigin,
+    /// To the site of the landing page (see `same_site`), ex: shop.example.com from
+    /// www.example.com
+    SameSite,
+}
+
+impl CrawlScope {
+    fn contains(self, landing_url: &Url, url: &Url) -> bool {
+        match self {
+            CrawlScope::SameOrigin => url.origin() == landing_url.origin(),
+            CrawlScope::SameSite => same_site(url, landing_url),
+        }
+    }
+}
+
+/// The techs of a site, over the pages of a crawl
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SiteAnalysis {
+    /// The landing page
+    pub url: String,
+    /// Every page that was scanned, in the order of the crawl
+    pub pages: Vec<Analysis>,
+    /// Every tech found on the pages, in the order they were first found
+    pub techs: Vec<SiteTech>,
+}
+
+/// A tech found on some pages of a site, with its most specific version and highest confidence
+/// over them, and the evidence of every page
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SiteTech {
+    #[serde(flatten)]
+    pub tech: Tech,
+    /// The pages it was found on, in the order of the crawl
+    pub pages: Vec<String>,
+}
+
+impl SiteTech {
+    fn add(&mut self, tech: &Tech, page: &str) {
+        // as for the rules of a page, the longest version wins
+        if tech.version.as_ref().map(String::len) > self.tech.version.as_ref().map(String::len) {
+            self.tech.version = tech.version.clone();
+            self.tech.cpe = tech.cpe.clone();
+        }
+        self.tech.confidence = self.tech.confidence.max(tech.confidence);
+        for name in tech.implied_by.iter() {
+            if !self.tech.implied_by.contains(name) {
+                self.tech.implied_by.push(name.clone());
+            }
+        }
+        for evidence in tech.evidence.iter() {
+            if !self.tech.evidence.contains(evidence) {
+                self.tech.evidence.push(evidence.clone());
+            }
+        }
+        self.pages.push(String::from(page));
+    }
+}
+
+/// Scans a site: the landing page, then the pages it links to within the scope, breadth first,
+/// down to `max_depth` links away and up to `max_pages` pages. A link that redirects out of the
+/// scope, or to a page that was already scanned, is left out. The pages share the fetches of
+/// their assets (see `ScanOptions::assets`).
+pub async fn scan_site(
+    engine: &Engine,
+    url: Url,
+    options: &ScanOptions,
+    crawl: &CrawlOptions,
+) -> SiteAnalysis {
+    let assets = AssetCache::new(options);
+    let mut pages = vec![];
+    let mut seen = HashSet::new();
+    seen.insert(without_fragment(&url));
+    // where the landing page was served from, after redirects
+    let mut landing_url = None;
+
+    let mut level = vec![url.clone()];
+    for depth in 0..=crawl.max_depth {
+        level.truncate(crawl.max_pages.saturating_sub(pages.len()));
+        if level.is_empty() {
+            break;
+        }
+        let scans = level
+            .drain(..)
+            .map(|url| scan_page(engine, url, options, &assets));
+        for (analysis, raw_data) in join_all(scans).await {
+            let raw_data = match raw_data {
+                Some(raw_data) => raw_data,
+                None => {
+                    pages.push(analysis);
+                    continue;
+                }
+            };
+            let final_url = Url::parse(&raw_data.final_url)
+                .map(|final_url| without_fragment(&final_url))
+                .ok();
+
+            let is_landing = landing_url.is_none();
+            let landing_url =
+                landing_url.get_or_insert_with(|| final_url.clone().unwrap_or_else(|| url.clone()));
+            if is_landing {
+                seen.insert(landing_url.clone());
+            } else {
+                // a link can redirect out of the scope, or to a page that was already scanned
+                let in_crawl = final_url.is_some_and(|final_url| {
+                    crawl.scope.contains(landing_url, &final_url)
+                        && (final_url.as_str() == analysis.url || seen.insert(final_url))
+                });
+                if !in_crawl {
+                    continue;
+                }
+            }
+
+            if depth < crawl.max_depth {
+                for link in links_to_follow(&raw_data, landing_url, crawl.scope) {
+                    if seen.insert(link.clone()) {
+                        level.push(link);
+                    }
+                }
+            }
+            pages.push(analysis);
+        }
+    }
+
+    let mut techs: Vec<SiteTech> = vec![];
+    for page in pages.iter() {
+        for tech in page.result.iter().flatten() {
+            match techs.iter_mut().find(|t| t.tech.name == tech.name) {
+                Some(site_tech) => site_tech.add(tech, &page.url),
+                None => techs.push(SiteTech {
+                    tech: tech.clone(),
+                    pages: vec![page.url.clone()],
+                }),
+            }
+        }
+    }
+    SiteAnalysis {
+        url: String::from(url.as_str()),
+        pages,
+        techs,
+    }
+}
+
+/// The links of the page to crawl: the http(s) ones in the scope that don't point to a file
+/// that isn't a page (an image, a PDF, ...), without their fragment
+fn links_to_follow(raw_data: &RawData, landing_url: &Url, scope: CrawlScope) -> Vec<Url> {
+    const FILES: [&str; 16] = [
+        "css", "gif", "gz", "ico", "jpeg", "jpg", "js", "json", "mp3", "mp4", "pdf", "png", "svg",
+        "webp", "xml", "zip",
+    ];
+
+    raw_data
+        .links
+        .iter()
+        .filter_map(|link| Url::parse(link).ok())
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .filter(|url| scope.contains(landing_url, url))
+        .filter(|url| {
+            let extension = url
+                .path()
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.rsplit_once('.'));
+            !extension.is_some_and(|(_, ext)| FILES.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .map(|url| without_fragment(&url))
+        .collect()
+}
+
+fn without_fragment(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url
+}
+
+/// Scans a page, fetching its assets through the cache when the options say so. The page is
+/// returned along with its analysis, unless it couldn't be fetched.
+async fn scan_page(
+    engine: &Engine,
+    url: Url,
+    options: &ScanOptions,
+    assets: &AssetCache,
+) -> (Analysis, Option<Arc<RawData>>) {
     let url_str = String::from(url.as_str());
-    match fetch(url).await {
+    let fetched = match (fetch(url, engine).await, &options.assets) {
+        (Ok(raw_data), Some(limits)) => Ok(fetch_assets(raw_data, limits, assets).await),
+        (fetched, _) => fetched,
+    };
+    match fetched {
         Ok(raw_data) => {
-            let analysis = wapp::check(raw_data).await;
-            Analysis {
+            let detections = engine.check(raw_data.clone()).await;
+            let analysis: Vec<Tech> = detections
+                .techs
+                .into_iter()
+                .filter(|tech| tech.confidence >= options.min_confidence)
+                .map(|tech| {
+                    if options.evidence {
+                        tech
+                    } else {
+                        Tech {
+                            evidence: vec![],
+                            ..tech
+                        }
+                    }
+                })
+                .collect();
+            // an exclusion is only reported when the tech that excludes is
+            let excluded = detections
+                .excluded
+                .into_iter()
+                .filter(|e| analysis.iter().any(|tech| tech.name == e.excluded_by))
+                .collect();
+            let analysis = Analysis {
                 url: url_str,
                 result: Ok(analysis),
-            }
+                excluded,
+            };
+            (analysis, Some(raw_data))
+        }
+        Err(err) => {
+            let analysis = Analysis {
+                url: url_str,
+                result: Err(err.to_string()),
+                excluded: vec![],
+            };
+            (analysis, None)
         }
-        Err(err) => Analysis {
-            url: url_str,
-            result: Err(err.to_string()),
-        },
     }
 }
 
-async fn fetch(url: Url) -> Result<Arc<wapp::RawData>, WappError> {
+async fn fetch(url: Url, engine: &Engine) -> Result<Arc<wapp::RawData>, WappError> {
     let client = reqwest::Client::new();
+    let requested_url = String::from(url.as_str());
     let res = client.get(url).send().await?;
     let mut cookies = vec![];
     {
@@ -101,32 +434,384 @@ async fn fetch(url: Url) -> Result<Arc<wapp::RawData>, WappError> {
         )));
     }
     let headers = res.headers().clone();
+    let page_url = res.url().clone();
     let html_string = res.text().await?;
-    let parsed_html = Html::parse_fragment(&html_string);
-    let selector = Selector::parse("meta").unwrap();
-    let mut script_tags = vec![];
-    for js in parsed_html.select(&Selector::parse("script").unwrap()) {
-        script_tags.push(js.html());
-    }
-
-    // Note: using a hashmap will not support two meta tags with the same name and different values,
-    // though I'm not sure if that's legal html.
-    let mut meta_tags = HashMap::new();
-    for meta in parsed_html.select(&selector) {
-        if let (Some(name), Some(content)) =
-            (meta.value().attr("name"), meta.value().attr("content"))
-        {
-            // eprintln!("META {} -> {}", name, content);
-            meta_tags.insert(String::from(name), String::from(content));
-        }
-    }
-    let raw_data = Arc::new(RawData {
-        headers,
-        cookies,
-        meta_tags,
-        script_tags,
-        html: html_string,
+    // parsing the page and reading its scripts would hold up the runtime
+    let engine = engine.clone();
+    let raw_data = tokio::task::spawn_blocking(move || {
+        RawData::with_dom(
+            &requested_url,
+            &page_url,
+            headers,
+            cookies,
+            html_string,
+            engine.ruleset(),
+        )
+    })
+    .await
+    .map_err(|err| WappError::Analyze(err.to_string()))?;
+
+    Ok(Arc::new(raw_data))
+}
+
+/// The external scripts and stylesheets of a page to fetch, as (is a script, URL): those that
+/// `limits` allow, once each
+fn assets_to_fetch<'r>(raw_data: &'r RawData, limits: &AssetLimits) -> Vec<(bool, &'r String)> {
+    let page_url = match Url::parse(&raw_data.final_url) {
+        Ok(page_url) => page_url,
+        Err(_) => return vec![],
+    };
+    let allowed = |url: &String| {
+        Url::parse(url).is_ok_and(|url| asset_allowed(&url, &page_url, &limits.hosts))
+    };
+
+    let mut seen = HashSet::new();
+    let scripts = raw_data.script_srcs.iter().map(|src| (true, src));
+    let stylesheets = raw_data.stylesheet_hrefs.iter().map(|href| (false, href));
+    scripts
+        .chain(stylesheets)
+        .filter(|(_, url)| allowed(url) && seen.insert(*url))
+        .take(limits.max_count)
+        .collect()
+}
+
+/// Whether an asset of the page can be fetched from the URL: over http(s), from the page's site
+/// or one of the hosts
+fn asset_allowed(url: &Url, page_url: &Url, hosts: &[String]) -> bool {
+    (url.scheme() == "http" || url.scheme() == "https")
+        && (same_site(url, page_url)
+            || hosts
+                .iter()
+                .any(|host| url.domain().is_some_and(|domain| is_within(domain, host))))
+}
+
+/// Whether to follow a redirect of an asset to the next URL, `previous` starting with the
+/// asset's URL: it has to be allowed as the asset was, its site standing for the page's
+fn follows_redirect(next: &Url, previous: &[Url], hosts: &[String]) -> bool {
+    const MAX_REDIRECTS: usize = 10;
+    previous.len() <= MAX_REDIRECTS
+        && previous
+            .first()
+            .is_some_and(|asset_url| asset_allowed(next, asset_url, hosts))
+}
+
+/// The client that fetches the assets: within the timeout, and only following the redirects
+/// that stay where assets can be fetched from
+fn asset_client(limits: &AssetLimits) -> reqwest::Result<reqwest::Client> {
+    let hosts = limits.hosts.clone();
+    let policy = reqwest::redirect::Policy::custom(move |attempt| {
+        if follows_redirect(attempt.url(), attempt.previous(), &hosts) {
+            attempt.follow()
+        } else {
+            attempt.stop()
+        }
     });
+    reqwest::Client::builder()
+        .timeout(limits.timeout)
+        .redirect(policy)
+        .build()
+}
+
+/// Fetches the external scripts and stylesheets of the page that `limits` allow, and adds
+/// their contents to it, on a blocking thread as the scripts are read for their globals. Those
+/// that can't be fetched are left out.
+async fn fetch_assets(
+    raw_data: Arc<RawData>,
+    limits: &AssetLimits,
+    assets: &AssetCache,
+) -> Arc<RawData> {
+    let to_fetch = assets_to_fetch(&raw_data, limits);
+    if to_fetch.is_empty() {
+        return raw_data;
+    }
+    let contents = join_all(
+        to_fetch
+            .iter()
+            .map(|(_, url)| assets.get(url, limits.max_size)),
+    )
+    .await;
+
+    let (mut scripts, mut stylesheets) = (HashMap::new(), HashMap::new());
+    for ((is_script, url), content) in to_fetch.into_iter().zip(contents) {
+        if let Some(content) = content {
+            let assets = if is_script {
+                &mut scripts
+            } else {
+                &mut stylesheets
+            };
+            assets.insert(url.clone(), String::clone(&content));
+        }
+    }
+    let page = raw_data.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut raw_data = RawData::clone(&page);
+        raw_data.add_assets(scripts, stylesheets);
+        Arc::new(raw_data)
+    })
+    .await
+    .unwrap_or(raw_data)
+}
+
+/// The external scripts and stylesheets of a scan, by URL, each fetched by the first page that
+/// needs it and shared with the others
+#[derive(Clone, Default)]
+struct AssetCache {
+    fetches: Arc<Mutex<HashMap<String, AssetFetch>>>,
+    /// The client of the scan, when its options fetch the assets (see `asset_client`)
+    client: Option<reqwest::Client>,
+}
+
+/// The fetch of an asset, which every page that needs it awaits
+type AssetFetch = Shared<BoxFuture<'static, Option<Arc<String>>>>;
+
+impl AssetCache {
+    fn new(options: &ScanOptions) -> AssetCache {
+        AssetCache {
+            client: options
+                .assets
+                .as_ref()
+                .and_then(|limits| asset_client(limits).ok()),
+            ..AssetCache::default()
+        }
+    }
+
+    async fn get(&self, url: &str, max_size: usize) -> Option<Arc<String>> {
+        let client = self.client.as_ref()?;
+        let fetch = self
+            .fetches
+            .lock()
+            .unwrap()
+            .entry(String::from(url))
+            .or_insert_with(|| {
+                fetch_asset(client.clone(), String::from(url), max_size)
+                    .boxed()
+                    .shared()
+            })
+            .clone();
+        fetch.await
+    }
+}
+
+/// The first `max_size` bytes of a script or stylesheet, if it can be fetched
+async fn fetch_asset(client: reqwest::Client, url: String, max_size: usize) -> Option<Arc<String>> {
+    let mut res = client.get(&url).send().await.ok()?;
+    if !res.status().is_success() {
+        return None;
+    }
+    let mut content = vec![];
+    while content.len() < max_size {
+        match res.chunk().await.ok()? {
+            Some(chunk) => content.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    content.truncate(max_size);
+    Some(Arc::new(String::from_utf8_lossy(&content).into_owned()))
+}
+
+/// Whether the URLs are on the same site, that is under the same registrable domain. Without
+/// the public suffix list, that is the last two labels of the host, or three under a country
+/// code with a generic second level (ex: example.co.uk). IP addresses have to be the same.
+pub fn same_site(a: &Url, b: &Url) -> bool {
+    match (a.domain(), b.domain()) {
+        (Some(a), Some(b)) => site(a).eq_ignore_ascii_case(site(b)),
+        _ => a.host() == b.host(),
+    }
+}
+
+/// The registrable domain of a domain name, as approximated by `same_site`
+fn site(domain: &str) -> &str {
+    const SECOND_LEVELS: [&str; 10] = [
+        "ac", "co", "com", "edu", "go", "gov", "ne", "net", "or", "org",
+    ];
+
+    let domain = domain.trim_end_matches('.');
+    let labels = domain.rsplit('.').collect::<Vec<_>>();
+    let count = match labels.as_slice() {
+        [tld, second, _, ..] if tld.len() == 2 && SECOND_LEVELS.contains(second) => 3,
+        _ => 2,
+    };
+    match domain.rmatch_indices('.').nth(count - 1) {
+        Some((i, _)) => &domain[i + 1..],
+        None => domain,
+    }
+}
+
+/// Whether the domain is the host or one of its subdomains
+fn is_within(domain: &str, host: &str) -> bool {
+    let domain = domain.to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    domain == host || domain.ends_with(&format!(".{}", host))
+}
 
-    Ok(raw_data)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_site() {
+        let same = |a: &str, b: &str| same_site(&Url::parse(a).unwrap(), &Url::parse(b).unwrap());
+        assert!(same(
+            "https://www.example.com/",
+            "http://static.example.com/app.js"
+        ));
+        assert!(same(
+            "https://shop.example.co.uk/",
+            "https://example.co.uk/"
+        ));
+        assert!(!same("https://example.co.uk/", "https://other.co.uk/"));
+        assert!(!same("https://example.com/", "https://example.net/"));
+        assert!(same("http://127.0.0.1:8080/", "http://127.0.0.1/app.js"));
+        assert!(!same("http://127.0.0.1/", "http://127.0.0.2/"));
+    }
+
+    #[test]
+    fn test_assets_to_fetch() {
+        let page_url = Url::parse("https://www.example.com/").unwrap();
+        let raw_data = RawData::new(
+            "https://www.example.com/",
+            &page_url,
+            reqwest::header::HeaderMap::new(),
+            vec![],
+            String::from(
+                r#"<link rel="stylesheet" href="/main.css"><link rel="icon" href="/icon.css">
+                <script src="https://cdn.example.com/app.js"></script>
+                <script src="https://code.jquery.com/jquery-3.5.1.min.js"></script>
+                <script src="https://tracker.example.net/t.js"></script>
+                <script src="data:text/javascript,1"></script>
+                <script src="/app.js"></script><script src="/app.js"></script>"#,
+            ),
+        );
+        let urls = |limits: &AssetLimits| {
+            let to_fetch = assets_to_fetch(&raw_data, limits);
+            to_fetch
+                .into_iter()
+                .map(|(is_script, url)| (is_script, url.as_str()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            urls(&AssetLimits::default()),
+            vec![
+                (true, "https://cdn.example.com/app.js"),
+                (true, "https://code.jquery.com/jquery-3.5.1.min.js"),
+                (true, "https://www.example.com/app.js"),
+                (false, "https://www.example.com/main.css"),
+            ]
+        );
+        let limits = AssetLimits {
+            max_count: 2,
+            max_size: 1024,
+            hosts: vec![String::from("example.net")],
+            ..AssetLimits::default()
+        };
+        assert_eq!(
+            urls(&limits),
+            vec![
+                (true, "https://cdn.example.com/app.js"),
+                (true, "https://tracker.example.net/t.js"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_follows_redirect() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        let hosts = AssetLimits::default().hosts;
+        let from = |asset_url: &str| vec![url(asset_url)];
+
+        let own = from("https://www.example.com/app.js");
+        assert!(follows_redirect(
+            &url("https://static.example.com/app.js"),
+            &own,
+            &hosts
+        ));
+        assert!(follows_redirect(
+            &url("https://unpkg.com/app@1.0.0/app.js"),
+            &own,
+            &hosts
+        ));
+        assert!(!follows_redirect(
+            &url("https://tracker.example.net/app.js"),
+            &own,
+            &hosts
+        ));
+        assert!(!follows_redirect(&url("file:///etc/passwd"), &own, &hosts));
+        assert!(!follows_redirect(&url("http://127.0.0.1/"), &own, &hosts));
+
+        let cdn = from("https://unpkg.com/app");
+        assert!(follows_redirect(
+            &url("https://unpkg.com/app@1.0.0/app.js"),
+            &cdn,
+            &hosts
+        ));
+        assert!(!follows_redirect(
+            &url("https://www.example.com/app.js"),
+            &cdn,
+            &hosts
+        ));
+
+        let chain = vec![url("https://www.example.com/app.js"); 11];
+        assert!(!follows_redirect(
+            &url("https://www.example.com/app.js"),
+            &chain,
+            &hosts
+        ));
+    }
+
+    #[test]
+    fn test_links_to_follow() {
+        let landing_url = Url::parse("https://www.example.com/").unwrap();
+        let raw_data = RawData::new(
+            "https://www.example.com/",
+            &landing_url,
+            reqwest::header::HeaderMap::new(),
+            vec![],
+            String::from(
+                r#"<a href="/blog/#comments">Blog</a> <a href="mailto:hi@example.com">Mail</a>
+                <a href="https://shop.example.com/cart">Cart</a> <a href="/brochure.PDF">PDF</a>
+                <a href="http://www.example.com/login">Log in</a> <a href="https://other.net/">Other</a>"#,
+            ),
+        );
+        let links = |scope| {
+            let links = links_to_follow(&raw_data, &landing_url, scope);
+            links.into_iter().map(String::from).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            links(CrawlScope::SameOrigin),
+            vec!["https://www.example.com/blog/"]
+        );
+        assert_eq!(
+            links(CrawlScope::SameSite),
+            vec![
+                "https://www.example.com/blog/",
+                "https://shop.example.com/cart",
+                "http://www.example.com/login",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_site_tech() {
+        let tech = |version: Option<&str>, confidence| Tech {
+            version: version.map(String::from),
+            confidence,
+            ..Tech::named("jQuery").unwrap()
+        };
+        let mut site_tech = SiteTech {
+            tech: tech(Some("3.5"), 50),
+            pages: vec![String::from("https://example.com/")],
+        };
+        site_tech.add(&tech(None, 100), "https://example.com/blog/");
+        site_tech.add(&tech(Some("3.5.1"), 50), "https://example.com/shop/");
+
+        assert_eq!(site_tech.tech.version, Some(String::from("3.5.1")));
+        assert_eq!(site_tech.tech.confidence, 100);
+        assert_eq!(site_tech.pages.len(), 3);
+        // the tech is flattened into the JSON
+        let json = serde_json::to_value(&site_tech).unwrap();
+        assert_eq!(json["name"], "jQuery");
+        assert_eq!(json["pages"][2], "https://example.com/shop/");
+    }
 }
@@ -1,15 +1,178 @@
 extern crate reqwest;
 extern crate url;
 
-use futures::future::join_all;
 use std::env;
 use std::io::{self, Read};
+use std::process;
 use url::Url;
-use wappalyzer::scan;
+use wappalyzer::corpus::{compare, load_corpus, SavedPage};
+use wappalyzer::diff::diff;
+#[cfg(feature = "js-engine")]
+use wappalyzer::js_engine::ScriptLimits;
+use wappalyzer::profile::profile;
+use wappalyzer::validate::{validate_embedded, validate_path};
+use wappalyzer::wapp::{AppsJsonData, Engine, Overlay};
+use wappalyzer::{scan_batch, scan_site, AssetLimits, CrawlOptions, CrawlScope, ScanOptions};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+
+    // wappalyzer validate [path/to/apps.json] [--json]
+    if args.get(1).map(String::as_str) == Some("validate") {
+        let report = match args.get(2) {
+            Some(path) => validate_path(path)?,
+            None => validate_embedded(),
+        };
+        if flags.iter().any(|flag| flag == "--json") {
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            println!("{}", report);
+        }
+        if !report.is_valid() {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let min_confidence = match flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--min-confidence="))
+    {
+        Some(min_confidence) => min_confidence.parse()?,
+        None => 0,
+    };
+    // --assets fetches the pages' scripts and stylesheets, --asset-host=cdn.example.com from
+    // another host too
+    let asset_hosts = flags
+        .iter()
+        .filter_map(|flag| flag.strip_prefix("--asset-host="))
+        .map(String::from);
+    let assets = if flags.iter().any(|flag| flag == "--assets") {
+        let mut limits = AssetLimits::default();
+        limits.hosts.extend(asset_hosts);
+        Some(limits)
+    } else {
+        None
+    };
+    let options = ScanOptions {
+        min_confidence,
+        evidence: flags.iter().any(|flag| flag == "--evidence"),
+        assets,
+    };
+    // wappalyzer diff [old/apps.json] new/apps.json [--json]
+    if args.get(1).map(String::as_str) == Some("diff") {
+        let (old, new) = match (args.get(2), args.get(3)) {
+            (Some(old), Some(new)) => {
+                (AppsJsonData::from_path(old)?, AppsJsonData::from_path(new)?)
+            }
+            (Some(new), None) => (AppsJsonData::embedded(), AppsJsonData::from_path(new)?),
+            _ => {
+                return Err("usage: wappalyzer diff [old/apps.json] new/apps.json [--json]".into())
+            }
+        };
+        let ruleset_diff = diff(&old, &new);
+        if flags.iter().any(|flag| flag == "--json") {
+            println!("{}", serde_json::to_string(&ruleset_diff)?);
+        } else {
+            println!("{}", ruleset_diff);
+        }
+        return Ok(());
+    }
+
+    // wappalyzer compare corpus/ [old/apps.json] new/apps.json [--json]
+    if args.get(1).map(String::as_str) == Some("compare") {
+        let (corpus, old, new) = match (args.get(2), args.get(3), args.get(4)) {
+            (Some(corpus), Some(old), Some(new)) => (
+                corpus,
+                AppsJsonData::from_path(old)?,
+                AppsJsonData::from_path(new)?,
+            ),
+            (Some(corpus), Some(new), None) => (
+                corpus,
+                AppsJsonData::embedded(),
+                AppsJsonData::from_path(new)?,
+            ),
+            _ => {
+                return Err(
+                    "usage: wappalyzer compare corpus/ [old/apps.json] new/apps.json [--json]"
+                        .into(),
+                )
+            }
+        };
+        let pages = corpus_pages(corpus)?;
+        let (old, new) = (
+            js_engine(Engine::new(old), &flags)?,
+            js_engine(Engine::new(new), &flags)?,
+        );
+        let (corpus_diff, skipped) = compare(&pages, &old, &new);
+        for err in skipped {
+            eprintln!("warning: skipped {}", err);
+        }
+        if flags.iter().any(|flag| flag == "--json") {
+            println!("{}", serde_json::to_string(&corpus_diff)?);
+        } else {
+            println!("{}", corpus_diff);
+        }
+        return Ok(());
+    }
+
+    let apps_json_data = match flags.iter().find_map(|flag| flag.strip_prefix("--apps=")) {
+        Some(path) => AppsJsonData::from_path(path)?,
+        None => AppsJsonData::embedded(),
+    };
+    let overlays = flags
+        .iter()
+        .filter_map(|flag| flag.strip_prefix("--overlay="))
+        .map(Overlay::from_path)
+        .collect::<Result<Vec<_>, _>>()?;
+    let (apps_json_data, warnings) = apps_json_data.with_overlays(overlays);
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let engine = js_engine(Engine::new(apps_json_data), &flags)?;
+
+    // wappalyzer profile corpus/ [--apps=path] [--overlay=path] [--top=N] [--json]
+    if args.get(1).map(String::as_str) == Some("profile") {
+        let corpus = args
+            .get(2)
+            .ok_or("usage: wappalyzer profile corpus/ [--top=N] [--json]")?;
+        let top = match flags.iter().find_map(|flag| flag.strip_prefix("--top=")) {
+            Some(top) => top.parse()?,
+            None => 20,
+        };
+        let (profile, skipped) = profile(&corpus_pages(corpus)?, &engine);
+        for err in skipped {
+            eprintln!("warning: skipped {}", err);
+        }
+        let report = profile.report(top);
+        if flags.iter().any(|flag| flag == "--json") {
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            println!("{}", report);
+        }
+        return Ok(());
+    }
+
+    // wappalyzer site https://example.com/ [--depth=N] [--pages=N] [--same-site]
+    if args.get(1).map(String::as_str) == Some("site") {
+        let url = args
+            .get(2)
+            .ok_or("usage: wappalyzer site URL [--depth=N] [--pages=N] [--same-site]")?;
+        let mut crawl = CrawlOptions::default();
+        if let Some(depth) = flags.iter().find_map(|flag| flag.strip_prefix("--depth=")) {
+            crawl.max_depth = depth.parse()?;
+        }
+        if let Some(pages) = flags.iter().find_map(|flag| flag.strip_prefix("--pages=")) {
+            crawl.max_pages = pages.parse()?;
+        }
+        if flags.iter().any(|flag| flag == "--same-site") {
+            crawl.scope = CrawlScope::SameSite;
+        }
+        let site_analysis = scan_site(&engine, Url::parse(url)?, &options, &crawl).await;
+        println!("{}", serde_json::to_string(&site_analysis)?);
+        return Ok(());
+    }
 
     let mut urls = vec![];
     if args.len() == 1 {
@@ -20,8 +183,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         urls.push(Url::parse(&String::from(&args[1]))?);
     }
 
-    let futures = urls.into_iter().map(scan).collect::<Vec<_>>();
-    let results = join_all(futures).await;
+    let results = scan_batch(&engine, urls, &options).await;
     for res in results {
         if let Ok(output) = serde_json::to_string(&res) {
             println!("{}", output);
@@ -30,10 +192,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// `--js-engine`: runs the pages' scripts in the embedded JS engine, for the js rules
+#[cfg(feature = "js-engine")]
+fn js_engine(engine: Engine, flags: &[String]) -> Result<Engine, Box<dyn std::error::Error>> {
+    if flags.iter().any(|flag| flag == "--js-engine") {
+        Ok(engine.with_js_engine(ScriptLimits::default()))
+    } else {
+        Ok(engine)
+    }
+}
+
+#[cfg(not(feature = "js-engine"))]
+fn js_engine(engine: Engine, flags: &[String]) -> Result<Engine, Box<dyn std::error::Error>> {
+    if flags.iter().any(|flag| flag == "--js-engine") {
+        Err("--js-engine needs a build with the js-engine feature".into())
+    } else {
+        Ok(engine)
+    }
+}
+
+/// The pages of a corpus, with a warning for each file that is skipped
+fn corpus_pages(dir: &str) -> Result<Vec<SavedPage>, Box<dyn std::error::Error>> {
+    let (pages, skipped) = load_corpus(dir)?;
+    for err in skipped {
+        eprintln!("warning: skipped {}", err);
+    }
+    Ok(pages)
+}
+
 fn strings_to_urls(domains: String) -> Vec<Url> {
     domains
         .split_terminator('\n')
-        .map(|s| Url::parse(s))
+        .map(Url::parse)
         .filter_map(Result::ok)
         .collect()
 }
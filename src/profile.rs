@@ -0,0 +1,272 @@
+//! Where the time goes when checking pages: every app's rules are run over a corpus of saved
+//! pages and timed, to find the rules that are worth tuning, or disabling in an overlay.
+
+use crate::corpus::SavedPage;
+use crate::wapp::{Engine, RuleTiming};
+use crate::WappError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The timings of a corpus, summed over its pages
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub pages: usize,
+    /// The time spent in the ruleset-wide prefilters (the html set and the indexes)
+    pub prefilter: Duration,
+    /// Every app that was checked on some page, by name
+    pub apps: Vec<AppProfile>,
+    /// Every rule that was run on some page, by app and rule
+    pub rules: Vec<RuleProfile>,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AppProfile {
+    pub name: String,
+    /// The time spent in `App::check`
+    pub time: Duration,
+    /// The number of pages the app was checked on (those that got past the prefilters)
+    pub checks: usize,
+    /// The number of pages the app was detected on
+    pub detections: usize,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RuleProfile {
+    pub app: String,
+    /// The apps.json field of the rule (headers, html, cookies, js, meta, dom, script, scripts,
+    /// text, css, url)
+    pub field: String,
+    /// The header, cookie, meta or js name, or the dom selector, for the fields that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// The rule value, with its tags
+    pub pattern: String,
+    pub time: Duration,
+    /// The number of pages the rule was run on
+    pub runs: usize,
+    /// The number of pages the rule matched
+    pub matches: usize,
+}
+
+/// Checks every page with every app of the engine, as `Engine::check_sync` does (without
+/// `implies`, `requires` and `excludes`), timing every app and every rule. The apps that get
+/// past the prefilters run all their html rules, including those that the html set would skip,
+/// so that each rule's own cost is measured. A page that can't be checked (ex: its URL doesn't
+/// parse) is skipped, and comes back with the error, which names it.
+pub fn profile(pages: &[SavedPage], engine: &Engine) -> (Profile, Vec<WappError>) {
+    let mut apps: HashMap<String, AppProfile> = HashMap::new();
+    let mut rules: HashMap<(String, &'static str, Option<String>, String), RuleProfile> =
+        HashMap::new();
+    let mut prefilter_time = Duration::default();
+    let mut skipped = vec![];
+
+    for page in pages {
+        let raw_data = match page.raw_data() {
+            Ok(raw_data) => engine.prepare(Arc::new(raw_data)),
+            Err(err) => {
+                skipped.push(err);
+                continue;
+            }
+        };
+        let start = Instant::now();
+        let prefilter = engine.ruleset().prefilter(&raw_data);
+        prefilter_time += start.elapsed();
+        let prefilter = prefilter.without_html_set();
+
+        for name in prefilter.apps() {
+            let app = match engine.apps_json_data().named(name) {
+                Some(app) => app,
+                None => continue,
+            };
+            let start = Instant::now();
+            let (tech, timings) =
+                app.check_profiled(engine.ruleset(), raw_data.clone(), &prefilter);
+            let elapsed = start.elapsed();
+
+            let app_profile = apps.entry(name.clone()).or_insert_with(|| AppProfile {
+                name: name.clone(),
+                ..AppProfile::default()
+            });
+            app_profile.time += elapsed;
+            app_profile.checks += 1;
+            app_profile.detections += tech.is_some() as usize;
+
+            for RuleTiming {
+                field,
+                key,
+                pattern,
+                elapsed,
+                matched,
+            } in timings
+            {
+                let rule = rules
+                    .entry((name.clone(), field, key.clone(), pattern.clone()))
+                    .or_insert_with(|| RuleProfile {
+                        app: name.clone(),
+                        field: String::from(field),
+                        key,
+                        pattern,
+                        ..RuleProfile::default()
+                    });
+                rule.time += elapsed;
+                rule.runs += 1;
+                rule.matches += matched as usize;
+            }
+        }
+    }
+
+    let mut apps = apps.into_values().collect::<Vec<_>>();
+    apps.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut rules = rules.into_values().collect::<Vec<_>>();
+    rules.sort_by(|a, b| {
+        (&a.app, &a.field, &a.key, &a.pattern).cmp(&(&b.app, &b.field, &b.key, &b.pattern))
+    });
+    let profile = Profile {
+        pages: pages.len() - skipped.len(),
+        prefilter: prefilter_time,
+        apps,
+        rules,
+    };
+    (profile, skipped)
+}
+
+impl Profile {
+    /// The `n` apps that took the most time
+    pub fn slowest_apps(&self, n: usize) -> Vec<&AppProfile> {
+        top(&self.apps, n, |app| app.time)
+    }
+
+    /// The `n` rules that took the most time
+    pub fn slowest_rules(&self, n: usize) -> Vec<&RuleProfile> {
+        top(&self.rules, n, |rule| rule.time)
+    }
+
+    /// The `n` rules that matched the most pages
+    pub fn noisiest_rules(&self, n: usize) -> Vec<&RuleProfile> {
+        top(&self.rules, n, |rule| rule.matches)
+    }
+
+    /// The top `n` of every list, to print or serialize
+    pub fn report(&self, n: usize) -> ProfileReport {
+        ProfileReport {
+            pages: self.pages,
+            prefilter: self.prefilter,
+            slowest_apps: self.slowest_apps(n).into_iter().cloned().collect(),
+            slowest_rules: self.slowest_rules(n).into_iter().cloned().collect(),
+            noisiest_rules: self.noisiest_rules(n).into_iter().cloned().collect(),
+        }
+    }
+}
+
+/// The highest by `key`, keeping the order of the items that are tied
+fn top<T, K: Ord, F: Fn(&T) -> K>(items: &[T], n: usize, key: F) -> Vec<&T> {
+    let mut items = items.iter().collect::<Vec<_>>();
+    items.sort_by_key(|item| std::cmp::Reverse(key(item)));
+    items.truncate(n);
+    items
+}
+
+/// The slowest apps, and the slowest and noisiest rules of a `Profile`
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ProfileReport {
+    pub pages: usize,
+    pub prefilter: Duration,
+    pub slowest_apps: Vec<AppProfile>,
+    pub slowest_rules: Vec<RuleProfile>,
+    pub noisiest_rules: Vec<RuleProfile>,
+}
+
+impl fmt::Display for RuleProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{} {}[{}]: {}", self.app, self.field, key, self.pattern),
+            None => write!(f, "{} {}: {}", self.app, self.field, self.pattern),
+        }
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} pages, {:?} in the prefilters",
+            self.pages, self.prefilter
+        )?;
+        writeln!(f, "slowest apps:")?;
+        for app in self.slowest_apps.iter() {
+            writeln!(
+                f,
+                "    {:>12?}  {} (checked on {} pages, detected on {})",
+                app.time, app.name, app.checks, app.detections
+            )?;
+        }
+        writeln!(f, "slowest rules:")?;
+        for rule in self.slowest_rules.iter() {
+            writeln!(f, "    {:>12?}  {} ({} runs)", rule.time, rule, rule.runs)?;
+        }
+        write!(f, "noisiest rules:")?;
+        for rule in self.noisiest_rules.iter() {
+            write!(f, "\n    {:>5} of {:>5}  {}", rule.matches, rule.runs, rule)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let engine: Engine = r#"{
+            "apps": {
+                "Nginx": { "cats": [1], "website": "", "headers": { "Server": "nginx" } },
+                "Acme": { "cats": [1], "website": "", "html": ["acme", "<link[^>]+?href=[^>]+acme\\.css"] },
+                "Widget": { "cats": [1], "website": "", "scripts": "widget\\.init" }
+            },
+            "categories": { "1": { "name": "Web servers", "priority": 1 } }
+        }"#
+        .parse()
+        .unwrap();
+
+        let pages: Vec<SavedPage> = serde_json::from_str(
+            r#"[
+                { "url": "https://a.example/", "headers": { "server": "nginx" }, "html": "<p>acme</p>" },
+                { "url": "https://b.example/", "headers": { "server": "nginx" }, "html": "<script>widget.init()</script>" },
+                { "url": "https://c.example/", "headers": { "server": "apache" }, "html": "<p>nothing</p>" },
+                { "url": "not a url", "headers": { "server": "nginx" }, "html": "<p>acme</p>" }
+            ]"#,
+        )
+        .unwrap();
+
+        // the page with an invalid URL is skipped, and doesn't stop the others
+        let (profile, skipped) = profile(&pages, &engine);
+        assert_eq!(profile.pages, 3);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].to_string().contains("not a url"));
+
+        let app = |name: &str| profile.apps.iter().find(|app| app.name == name).unwrap();
+        assert_eq!((app("Nginx").checks, app("Nginx").detections), (3, 2));
+        // Acme only has html patterns, and the html set only lets it through on a.example
+        assert_eq!((app("Acme").checks, app("Acme").detections), (1, 1));
+        assert_eq!((app("Widget").checks, app("Widget").detections), (3, 1));
+        // but it then runs each of its html rules, even the one the set didn't match
+        let rule = |pattern: &str| profile.rules.iter().find(|r| r.pattern == pattern).unwrap();
+        assert_eq!((rule("acme").runs, rule("acme").matches), (1, 1));
+        let link = rule("<link[^>]+?href=[^>]+acme\\.css");
+        assert_eq!((link.runs, link.matches), (1, 0));
+
+        let noisiest = profile.noisiest_rules(1);
+        assert_eq!(noisiest[0].app, "Nginx");
+        assert_eq!(noisiest[0].key.as_deref(), Some("Server"));
+        assert_eq!((noisiest[0].runs, noisiest[0].matches), (3, 2));
+
+        let report = profile.report(2);
+        assert_eq!(report.slowest_apps.len(), 2);
+        assert_eq!(report.slowest_rules.len(), 2);
+        assert!(report.to_string().contains("Nginx headers[Server]: nginx"));
+    }
+}
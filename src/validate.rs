@@ -0,0 +1,553 @@
+//! Checks a ruleset before it's used: against `schema.json`, then for what the schema can't
+//! tell, see `check_apps`. A malformed rule otherwise just never fires.
+
+use crate::cpe::Cpe;
+use crate::js;
+use crate::wapp::{
+    self, AppsJsonData, CompiledRuleset, DomCheck, FallbackCounts, Matcher, Pattern, MAX_CONFIDENCE,
+};
+use crate::WappError;
+use regex::Regex;
+use scraper::Selector;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+lazy_static! {
+    static ref SCHEMA: Value =
+        serde_json::from_str(include_str!("../schema.json")).expect("Error loading schema.json");
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// The ruleset can't be loaded, or a rule will never fire as intended
+    Error,
+    /// Most likely a mistake, but the rule still works
+    Warning,
+}
+
+/// Something wrong with a ruleset
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Problem {
+    pub level: Level,
+    /// Where it is, as a JSON pointer into the apps.json, ex: "/apps/WordPress/html"
+    pub path: String,
+    pub message: String,
+}
+impl Problem {
+    pub fn new(level: Level, path: String, message: String) -> Problem {
+        Problem {
+            level,
+            path,
+            message,
+        }
+    }
+
+    pub fn error(path: String, message: String) -> Problem {
+        Problem::new(Level::Error, path, message)
+    }
+
+    pub fn warning(path: String, message: String) -> Problem {
+        Problem::new(Level::Warning, path, message)
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", level, self.path, self.message)
+    }
+}
+
+/// The outcome of validating a ruleset
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub errors: usize,
+    pub warnings: usize,
+    pub problems: Vec<Problem>,
+    /// How many apps the fallback regex engine recovers, when the apps could be loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<FallbackCounts>,
+}
+impl Report {
+    pub fn new(problems: Vec<Problem>) -> Report {
+        let errors = problems.iter().filter(|p| p.level == Level::Error).count();
+        Report {
+            errors,
+            warnings: problems.len() - errors,
+            problems,
+            fallback: None,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors == 0
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for problem in self.problems.iter() {
+            writeln!(f, "{}", problem)?;
+        }
+        if let Some(fallback) = &self.fallback {
+            writeln!(f, "{}", fallback)?;
+        }
+        write!(f, "{} errors, {} warnings", self.errors, self.warnings)
+    }
+}
+
+/// Validates an apps.json, or the single-file version of the technologies/*.json format
+pub fn validate(apps_json: &str) -> Report {
+    match serde_json::from_str(apps_json) {
+        Ok(apps_json) => validate_value(apps_json),
+        Err(err) => Report::new(vec![Problem::error(String::new(), err.to_string())]),
+    }
+}
+
+/// Validates an apps.json file, or a directory in the technologies/*.json format
+pub fn validate_path<P: AsRef<Path>>(path: P) -> Result<Report, WappError> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let (apps, categories): (HashMap<String, Value>, Value) =
+            wapp::read_technologies_dir(path)?;
+        let mut apps_json = serde_json::Map::new();
+        apps_json.insert(String::from("categories"), categories);
+        apps_json.insert(
+            String::from("apps"),
+            Value::Object(apps.into_iter().collect()),
+        );
+        Ok(validate_value(Value::Object(apps_json)))
+    } else {
+        let apps_json: Value = serde_json::from_reader(wapp::open(path)?).map_err(|err| {
+            WappError::Ruleset(format!("Error loading {}: {}", path.display(), err))
+        })?;
+        Ok(validate_value(apps_json))
+    }
+}
+
+/// Validates the apps.json embedded at build time
+pub fn validate_embedded() -> Report {
+    validate(wapp::APPS_JSON_TEXT)
+}
+
+fn validate_value(mut apps_json: Value) -> Report {
+    // the schema predates the technologies/*.json format, where the apps are `technologies`
+    if let Value::Object(object) = &mut apps_json {
+        if !object.contains_key("apps") {
+            if let Some(technologies) = object.remove("technologies") {
+                object.insert(String::from("apps"), technologies);
+            }
+        }
+    }
+
+    let mut problems = vec![];
+    check_schema(&apps_json, &SCHEMA, &mut vec![], &mut problems);
+    match AppsJsonData::from_value(apps_json) {
+        Ok(apps_json_data) => {
+            problems.extend(check_apps(&apps_json_data));
+            let fallback = CompiledRuleset::compile(&apps_json_data).fallback_counts();
+            Report {
+                fallback: Some(fallback),
+                ..Report::new(problems)
+            }
+        }
+        Err(err) => {
+            problems.push(Problem::error(
+                String::new(),
+                format!("can't be loaded: {}", err),
+            ));
+            Report::new(problems)
+        }
+    }
+}
+
+/// Joins the parts into a JSON pointer (RFC 6901), ex: ["apps", "Wix", "js"] into "/apps/Wix/js"
+pub fn pointer(parts: &[&str]) -> String {
+    parts
+        .iter()
+        .map(|part| format!("/{}", part.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Checks a value against the parts of JSON Schema that `schema.json` uses (it follows draft 3,
+/// where `required` is a boolean on the property): `type`, `properties`, `patternProperties`,
+/// `additionalProperties`, `items` and `required`
+fn check_schema(
+    value: &Value,
+    schema: &Value,
+    path: &mut Vec<String>,
+    problems: &mut Vec<Problem>,
+) {
+    let here = |path: &[String]| pointer(&path.iter().map(String::as_str).collect::<Vec<_>>());
+
+    if let Some(types) = schema.get("type") {
+        let types = match types {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => types.as_str().into_iter().collect::<Vec<_>>(),
+        };
+        if !types.iter().any(|t| has_type(value, t)) {
+            problems.push(Problem::error(
+                here(path),
+                format!(
+                    "expected {}, found {}",
+                    types.join(" or "),
+                    type_name(value)
+                ),
+            ));
+            return;
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let pattern_properties = schema
+                .get("patternProperties")
+                .and_then(Value::as_object)
+                .map(|patterns| {
+                    patterns
+                        .iter()
+                        .filter_map(|(pattern, schema)| Some((Regex::new(pattern).ok()?, schema)))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+
+            for (name, property) in properties.into_iter().flatten() {
+                let required = property.get("required") == Some(&Value::Bool(true))
+                    || schema
+                        .get("required")
+                        .and_then(Value::as_array)
+                        .is_some_and(|required| required.iter().any(|r| r == name.as_str()));
+                if required && !object.contains_key(name) {
+                    problems.push(Problem::error(here(path), format!("'{}' is missing", name)));
+                }
+            }
+
+            for (name, property_value) in object.iter() {
+                let mut schemas = pattern_properties
+                    .iter()
+                    .filter(|(pattern, _)| pattern.is_match(name))
+                    .map(|(_, schema)| *schema)
+                    .collect::<Vec<_>>();
+                schemas.extend(properties.and_then(|properties| properties.get(name)));
+
+                path.push(name.clone());
+                if schemas.is_empty() {
+                    match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => problems.push(Problem::error(
+                            here(path),
+                            String::from("isn't a known property"),
+                        )),
+                        Some(additional) => {
+                            check_schema(property_value, additional, path, problems)
+                        }
+                        None => (),
+                    }
+                }
+                for schema in schemas {
+                    check_schema(property_value, schema, path, problems);
+                }
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i.to_string());
+                    check_schema(item, item_schema, path, problems);
+                    path.pop();
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn has_type(value: &Value, schema_type: &str) -> bool {
+    match schema_type {
+        "integer" => value.is_i64() || value.is_u64(),
+        "any" => true,
+        _ => type_name(value) == schema_type,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// What's wrong with the apps beyond the shape of their JSON: regexes that don't compile,
+/// tags that can't be parsed, CPE names that can't be parsed, and `implies`, `excludes`,
+/// `requires` or categories that name apps or categories that don't exist
+pub fn check_apps(apps_json_data: &AppsJsonData) -> Vec<Problem> {
+    let mut problems = vec![];
+    for app in apps_json_data.apps() {
+        let path = |field: &str, key: Option<&String>| {
+            let mut parts = vec!["apps", app.name(), field];
+            parts.extend(key.map(String::as_str));
+            pointer(&parts)
+        };
+
+        for (field, key, value) in app.rules() {
+            match Matcher::compile(&Pattern::parse(value).regex) {
+                Ok(regex) => {
+                    if regex.is_fallback() {
+                        problems.push(Problem::warning(
+                            path(field, key),
+                            format!("'{}' needs the fallback regex engine", value),
+                        ));
+                    }
+                    problems.extend(
+                        tag_problems(value, regex.captures_len())
+                            .into_iter()
+                            .map(|(level, message)| Problem::new(level, path(field, key), message)),
+                    )
+                }
+                Err(err) => problems.push(Problem::error(
+                    path(field, key),
+                    format!("'{}' doesn't compile: {}", value, err),
+                )),
+            }
+        }
+        let mut selectors = HashSet::new();
+        for (selector, check, _) in app.dom_rules() {
+            if selectors.insert(selector) && Selector::parse(selector).is_err() {
+                problems.push(Problem::error(
+                    path("dom", Some(selector)),
+                    format!("'{}' isn't a CSS selector", selector),
+                ));
+            }
+            if let DomCheck::Property(name) = check {
+                problems.push(Problem::warning(
+                    path("dom", Some(selector)),
+                    format!("the property '{}' needs a browser, and never matches", name),
+                ));
+            }
+        }
+        for name in app
+            .js_names()
+            .into_iter()
+            .filter(|name| !js::is_property_path(name))
+        {
+            problems.push(Problem::warning(
+                path("js", Some(name)),
+                format!("'{}' isn't a property path, and never matches", name),
+            ));
+        }
+        if let Some(Err(err)) = app.cpe().map(Cpe::parse) {
+            problems.push(Problem::error(path("cpe", None), err));
+        }
+
+        for (field, names) in app.references().iter() {
+            for name in names.iter() {
+                // ex: "PHP\;confidence:50"
+                if apps_json_data
+                    .named(&Pattern::parse(name).app_name())
+                    .is_none()
+                {
+                    problems.push(Problem::error(
+                        path(field, None),
+                        format!("'{}' isn't an app", name),
+                    ));
+                }
+                problems.extend(
+                    tag_problems(name, 1)
+                        .into_iter()
+                        .map(|(level, message)| Problem::new(level, path(field, None), message)),
+                );
+            }
+        }
+
+        for (field, ids) in [
+            ("cats", app.cats()),
+            ("requiresCategory", app.requires_category()),
+        ]
+        .iter()
+        {
+            for id in ids
+                .iter()
+                .filter(|id| apps_json_data.category(**id).is_none())
+            {
+                problems.push(Problem::error(
+                    path(field, None),
+                    format!("category {} doesn't exist", id),
+                ));
+            }
+        }
+    }
+
+    problems.sort_by(|a, b| a.path.cmp(&b.path));
+    problems
+}
+
+/// What `parse` glosses over in the tags of a rule value: confidences that aren't a number
+/// from 0 to 100, versions that use a group the regex doesn't have, and unknown tags.
+/// `groups` counts the whole match as group 0, as `Regex::captures_len` does.
+fn tag_problems(maybe_regex: &str, groups: usize) -> Vec<(Level, String)> {
+    let mut problems = vec![];
+    for tag in maybe_regex.split("\\;").skip(1) {
+        if let Some(template) = tag.strip_prefix("version:") {
+            // `\1` ... `\9`, as in `resolve_version`
+            let used = template
+                .split('\\')
+                .skip(1)
+                .filter_map(|after| after.chars().next()?.to_digit(10));
+            for group in used.filter(|group| *group as usize >= groups) {
+                problems.push((
+                    Level::Error,
+                    format!(
+                        "the version '{}' uses group {}, but the regex has {}",
+                        template,
+                        group,
+                        groups - 1
+                    ),
+                ));
+            }
+        } else if let Some(value) = tag.strip_prefix("confidence:") {
+            match value.trim().parse::<u32>() {
+                Ok(confidence) if confidence <= MAX_CONFIDENCE => (),
+                _ => problems.push((
+                    Level::Error,
+                    format!("the confidence '{}' isn't a number from 0 to 100", value),
+                )),
+            }
+        } else if !tag.is_empty() {
+            problems.push((Level::Warning, format!("unknown tag '{}'", tag)));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let report = validate(
+            r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "Acme CMS": {
+                        "cats": [1, 7],
+                        "website": "https://acme.example",
+                        "html": ["acme(\\d", "acme-([\\d.]+)\\;version:\\2", "acme\\;confidence:high"],
+                        "meta": { "generator": "Acme\\;confidnce:50" },
+                        "implies": ["PHP", "Acme Server\\;confidence:50"],
+                        "cpe": "acme",
+                        "favicon": "acme.png"
+                    },
+                    "PHP": { "cats": [1], "website": "https://php.net", "headers": { "X-Powered-By": "PHP" } },
+                    "Broken": { "website": 3 }
+                }
+            }"#,
+        );
+
+        let problems = report
+            .problems
+            .iter()
+            .map(|p| (p.level, p.path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                (Level::Error, "/apps/Acme CMS/favicon"),
+                (Level::Error, "/apps/Broken"),
+                (Level::Error, "/apps/Broken/website"),
+                (Level::Error, ""),
+            ]
+        );
+        assert!(!report.is_valid());
+
+        // once the JSON can be loaded, the rules themselves are checked
+        let report = validate(
+            r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "Acme CMS": {
+                        "cats": [1, 7],
+                        "website": "https://acme.example",
+                        "html": ["acme(\\d", "acme-([\\d.]+)\\;version:\\2", "acme\\;confidence:high"],
+                        "meta": { "generator": "Acme\\;confidnce:50" },
+                        "implies": ["PHP", "Acme Server\\;confidence:50"],
+                        "cpe": "acme",
+                        "dom": { "div[": { "exists": "" }, ".acme": { "properties": { "_acme": "" } } }
+                    },
+                    "PHP": { "cats": [1], "website": "https://php.net", "headers": { "X-Powered-By": "PHP" } }
+                }
+            }"#,
+        );
+        let messages = report
+            .problems
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 9, "{:#?}", messages);
+        assert_eq!(
+            messages[0],
+            "error: /apps/Acme CMS/cats: category 7 doesn't exist"
+        );
+        assert!(messages.contains(&String::from(
+            "error: /apps/Acme CMS/implies: 'Acme Server\\;confidence:50' isn't an app"
+        )));
+        assert!(messages.contains(&String::from(
+            "error: /apps/Acme CMS/html: the version '\\2' uses group 2, but the regex has 1"
+        )));
+        assert!(messages.contains(&String::from(
+            "error: /apps/Acme CMS/html: the confidence 'high' isn't a number from 0 to 100"
+        )));
+        assert!(messages.contains(&String::from(
+            "warning: /apps/Acme CMS/meta/generator: unknown tag 'confidnce:50'"
+        )));
+        assert!(messages
+            .iter()
+            .any(|m| m.starts_with("error: /apps/Acme CMS/html: 'acme(\\d' doesn't compile")));
+        assert!(messages
+            .iter()
+            .any(|m| m.starts_with("error: /apps/Acme CMS/cpe: ")));
+        assert!(messages.contains(&String::from(
+            "error: /apps/Acme CMS/dom/div[: 'div[' isn't a CSS selector"
+        )));
+        assert!(messages.contains(&String::from(
+            "warning: /apps/Acme CMS/dom/.acme: the property '_acme' needs a browser, and never matches"
+        )));
+        assert_eq!((report.errors, report.warnings), (7, 2));
+    }
+
+    #[test]
+    fn test_validate_embedded() {
+        let report = validate_embedded();
+        // only warnings: the patterns that need the fallback regex engine (lookarounds, ...), and
+        // the js names that aren't property paths
+        assert_eq!(report.errors, 0);
+        let (js_names, fallbacks): (Vec<_>, Vec<_>) = report.problems.iter().partition(|p| {
+            p.message
+                .ends_with("isn't a property path, and never matches")
+        });
+        assert_eq!(fallbacks.len(), wapp::compiled_ruleset().fallbacks().len());
+        assert_eq!(js_names.len(), 5);
+        assert_eq!(
+            report.fallback,
+            Some(wapp::compiled_ruleset().fallback_counts())
+        );
+    }
+
+    #[test]
+    fn test_pointer() {
+        assert_eq!(pointer(&["apps", "Wix", "js"]), "/apps/Wix/js");
+        assert_eq!(pointer(&["apps", "A/B~C"]), "/apps/A~1B~0C");
+    }
+}
@@ -1,12 +1,25 @@
 use serde::de;
 use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
+use crate::cpe::Cpe;
+use crate::js::{self, Global};
+#[cfg(feature = "js-engine")]
+use crate::js_engine::{self, ScriptLimits};
+use crate::WappError;
 use futures::future::join_all;
-use regex::Regex;
-use std::collections::HashMap;
+use regex::{Regex, RegexSet, RegexSetBuilder};
+use scraper::{Html, Selector};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::marker::PhantomData;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::Url;
 
 extern crate lazy_static;
 
@@ -15,116 +28,942 @@ extern crate lazy_static;
 // in the build dir. Here, we include this constant.
 include!(concat!(env!("OUT_DIR"), "/apps.json.rs"));
 
+/// The html set holds hundreds of patterns, well over the default size limit of the regex crate
+const HTML_SET_SIZE_LIMIT: usize = 256 * (1 << 20);
+
+/// How far fancy-regex backtracks on a page before giving up on a fallback pattern (as a miss)
+const FALLBACK_BACKTRACK_LIMIT: usize = 100_000;
+
+/// Confidence of a rule without a `\;confidence:` tag, and the cap for the summed confidence of an app
+pub const MAX_CONFIDENCE: u32 = 100;
+
+/// A selector like `div` can select much of a page: the elements past these aren't checked
+const MAX_DOM_ELEMENTS: usize = 100;
+
 /// A very simple representation for cookie data
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Cookie {
     pub name: String,
     pub value: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawData {
+    /// The URL that was requested
+    pub url: String,
+    /// The URL the page was served from, after following redirects
+    pub final_url: String,
     pub headers: reqwest::header::HeaderMap,
     pub cookies: Vec<Cookie>,
     pub meta_tags: HashMap<String, String>,
     pub script_tags: Vec<String>,
+    /// The `src` of the page's `<script>` tags, resolved against the page URL and `<base href>`
+    pub script_srcs: Vec<String>,
     pub html: String,
+    /// The text of the page, as a browser would show it: without the scripts and styles
+    pub text: String,
+    /// The contents of the page's `<style>` tags
+    pub css: Vec<String>,
+    /// The `href` of the page's stylesheet links, resolved as the script srcs are
+    pub stylesheet_hrefs: Vec<String>,
+    /// The `href` of the page's `<a>` links, resolved as the script srcs are
+    pub links: Vec<String>,
+    /// The globals that the page's inline scripts define, by property path (see `js::globals`)
+    pub js_globals: HashMap<String, Global>,
+    /// The contents of the page's external scripts that were fetched, by src (as in
+    /// `script_srcs`)
+    pub external_scripts: HashMap<String, String>,
+    /// The contents of the page's stylesheets that were fetched, by href (as in
+    /// `stylesheet_hrefs`)
+    pub external_stylesheets: HashMap<String, String>,
+    /// The elements that the selectors of the dom rules select, by selector (see
+    /// `RawData::with_dom` and `CompiledRuleset::select_dom`)
+    pub dom: HashMap<String, Vec<DomElement>>,
 }
 
-pub async fn check(raw_data: Arc<RawData>) -> Vec<Tech> {
-    let mut futures: Vec<tokio::task::JoinHandle<Option<Tech>>> = vec![];
+/// An element selected by a dom rule: its text and its attributes
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct DomElement {
+    pub text: String,
+    pub attributes: HashMap<String, String>,
+}
 
-    for app in APPS_JSON_DATA.apps.values() {
-        futures.push(app.tech_tokio(raw_data.clone()));
+impl RawData {
+    /// Extracts what the rules look at from a page: its meta tags, scripts, stylesheets and
+    /// text. `url` is the URL that was requested, and `page_url` the one the page was served
+    /// from, against which the script srcs are resolved.
+    pub fn new(
+        url: &str,
+        page_url: &Url,
+        headers: reqwest::header::HeaderMap,
+        cookies: Vec<Cookie>,
+        html: String,
+    ) -> RawData {
+        RawData::parse(url, page_url, headers, cookies, html, None)
     }
 
-    join_all(futures)
-        .await
-        .iter()
-        .filter_map(|r| r.as_ref().ok())
-        .filter(|o| o.is_some())
-        .map(|r| r.as_ref().unwrap().to_owned())
+    /// Same as `new`, also selecting the elements that the ruleset's dom rules look at from the
+    /// same parse of the page, which `Engine::prepare` would otherwise parse again
+    pub fn with_dom(
+        url: &str,
+        page_url: &Url,
+        headers: reqwest::header::HeaderMap,
+        cookies: Vec<Cookie>,
+        html: String,
+        ruleset: &CompiledRuleset,
+    ) -> RawData {
+        RawData::parse(url, page_url, headers, cookies, html, Some(ruleset))
+    }
+
+    fn parse(
+        url: &str,
+        page_url: &Url,
+        headers: reqwest::header::HeaderMap,
+        cookies: Vec<Cookie>,
+        html: String,
+        ruleset: Option<&CompiledRuleset>,
+    ) -> RawData {
+        // as a browser would: a dom selector can start from `body`
+        let parsed_html = Html::parse_document(&html);
+        let selector = Selector::parse("meta").unwrap();
+        let mut script_tags = vec![];
+        let mut js_globals = HashMap::new();
+        for js in parsed_html.select(&Selector::parse("script").unwrap()) {
+            script_tags.push(js.html());
+            if js::is_javascript(js.value().attr("type")) {
+                js_globals.extend(js::globals(&js.text().collect::<String>()));
+            }
+        }
+        let script_srcs = script_srcs(&parsed_html, page_url);
+        let stylesheet_hrefs = stylesheet_hrefs(&parsed_html, page_url);
+        let base_url = base_url(&parsed_html, page_url);
+        let links = parsed_html
+            .select(&Selector::parse("a[href]").unwrap())
+            .filter_map(|link| link.value().attr("href"))
+            .map(|href| resolve(&base_url, href))
+            .collect();
+        let css = parsed_html
+            .select(&Selector::parse("style").unwrap())
+            .map(|style| style.text().collect::<String>())
+            .collect();
+        let text = page_text(&parsed_html);
+
+        // Note: using a hashmap will not support two meta tags with the same name and different values,
+        // though I'm not sure if that's legal html.
+        let mut meta_tags = HashMap::new();
+        for meta in parsed_html.select(&selector) {
+            if let (Some(name), Some(content)) =
+                (meta.value().attr("name"), meta.value().attr("content"))
+            {
+                meta_tags.insert(String::from(name), String::from(content));
+            }
+        }
+        let dom = ruleset
+            .map(|ruleset| select(&parsed_html, ruleset.dom_selectors.iter()))
+            .unwrap_or_default();
+
+        RawData {
+            url: String::from(url),
+            final_url: String::from(page_url.as_str()),
+            headers,
+            cookies,
+            meta_tags,
+            script_tags,
+            script_srcs,
+            html,
+            text,
+            css,
+            stylesheet_hrefs,
+            links,
+            js_globals,
+            external_scripts: HashMap::new(),
+            external_stylesheets: HashMap::new(),
+            dom,
+        }
+    }
+
+    /// Adds the contents of the page's external scripts and stylesheets, by URL (as in
+    /// `script_srcs` and `stylesheet_hrefs`). The scripts are read for the globals they
+    /// define, as the inline ones are, without overriding those.
+    pub fn add_assets(
+        &mut self,
+        scripts: HashMap<String, String>,
+        stylesheets: HashMap<String, String>,
+    ) {
+        for src in self.script_srcs.iter() {
+            if let Some(script) = scripts.get(src) {
+                for (path, global) in js::globals(script) {
+                    self.js_globals.entry(path).or_insert(global);
+                }
+            }
+        }
+        self.external_scripts.extend(scripts);
+        self.external_stylesheets.extend(stylesheets);
+    }
+
+    /// The page's scripts, as (src, content): the inline ones first, then the external ones
+    /// that were fetched, in the order of the page
+    fn scripts(&self) -> impl Iterator<Item = (Option<&String>, &String)> {
+        let external = self
+            .script_srcs
+            .iter()
+            .filter_map(move |src| Some((Some(src), self.external_scripts.get(src)?)));
+        self.script_tags
+            .iter()
+            .map(|tag| (None, tag))
+            .chain(external)
+    }
+
+    /// The page's stylesheets, as (href, content): its style tags first, then the external
+    /// ones that were fetched, in the order of the page
+    fn stylesheets(&self) -> impl Iterator<Item = (Option<&String>, &String)> {
+        let external = self
+            .stylesheet_hrefs
+            .iter()
+            .filter_map(move |href| Some((Some(href), self.external_stylesheets.get(href)?)));
+        self.css.iter().map(|css| (None, css)).chain(external)
+    }
+}
+
+/// The `src` of every `<script>` tag, made absolute using the `<base href>` if there is one,
+/// and otherwise the URL of the page
+fn script_srcs(parsed_html: &Html, page_url: &Url) -> Vec<String> {
+    let base_url = base_url(parsed_html, page_url);
+    parsed_html
+        .select(&Selector::parse("script[src]").unwrap())
+        .filter_map(|script| script.value().attr("src"))
+        .map(|src| resolve(&base_url, src))
+        .collect()
+}
+
+/// The `href` of every `<link rel="stylesheet">`, made absolute as the script srcs are
+fn stylesheet_hrefs(parsed_html: &Html, page_url: &Url) -> Vec<String> {
+    let base_url = base_url(parsed_html, page_url);
+    parsed_html
+        .select(&Selector::parse("link[rel][href]").unwrap())
+        .filter(|link| {
+            let rel = link.value().attr("rel").unwrap_or_default();
+            rel.split_ascii_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("stylesheet"))
+        })
+        .filter_map(|link| link.value().attr("href"))
+        .map(|href| resolve(&base_url, href))
+        .collect()
+}
+
+/// The URL that the page's relative URLs are resolved against
+fn base_url(parsed_html: &Html, page_url: &Url) -> Url {
+    parsed_html
+        .select(&Selector::parse("base[href]").unwrap())
+        .next()
+        .and_then(|base| base.value().attr("href"))
+        .and_then(|href| page_url.join(href).ok())
+        .unwrap_or_else(|| page_url.clone())
+}
+
+fn resolve(base_url: &Url, url: &str) -> String {
+    match base_url.join(url.trim()) {
+        Ok(url) => String::from(url.as_str()),
+        Err(_) => String::from(url),
+    }
+}
+
+/// The elements that each selector selects, by selector, at most `MAX_DOM_ELEMENTS` of each
+fn select<'s>(
+    parsed_html: &Html,
+    selectors: impl Iterator<Item = (&'s String, &'s Selector)>,
+) -> HashMap<String, Vec<DomElement>> {
+    selectors
+        .map(|(source, selector)| {
+            let elements = parsed_html
+                .select(selector)
+                .take(MAX_DOM_ELEMENTS)
+                .map(|element| DomElement {
+                    text: element.text().collect(),
+                    attributes: element
+                        .value()
+                        .attrs()
+                        .map(|(name, value)| (String::from(name), String::from(value)))
+                        .collect(),
+                })
+                .collect();
+            (source.clone(), elements)
+        })
+        .collect()
+}
+
+/// The text nodes of the page joined by spaces, leaving out those of the elements that aren't
+/// shown (scripts, styles, the head, ...)
+fn page_text(parsed_html: &Html) -> String {
+    const HIDDEN: [&str; 6] = ["head", "script", "style", "noscript", "template", "title"];
+
+    parsed_html
+        .root_element()
+        .descendants()
+        .filter(|node| {
+            !node
+                .ancestors()
+                .filter_map(|ancestor| ancestor.value().as_element())
+                .any(|element| HIDDEN.contains(&element.name()))
+        })
+        .filter_map(|node| node.value().as_text())
+        .map(|text| text.trim())
+        .filter(|text| !text.is_empty())
         .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The outcome of checking a page against the apps
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Detections {
+    pub techs: Vec<Tech>,
+    /// Techs that were detected but dropped because another detected app `excludes` them
+    pub excluded: Vec<Exclusion>,
+}
+
+/// Orders techs by decreasing confidence, then by name
+fn by_confidence(a: &Tech, b: &Tech) -> std::cmp::Ordering {
+    b.confidence
+        .cmp(&a.confidence)
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+/// A detection suppressed by the `excludes` of another detected app
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Exclusion {
+    pub name: String,
+    pub excluded_by: String,
 }
 
-// lazy_static! {
-//     static ref APPS_JSON_DATA: AppsJsonData = {
-//         let apps_json = fs::read_to_string(APPS_JSON_PATH)
-//             .expect("Something went wrong reading the apps.json file");
-//         let mut apps_json_data: AppsJsonData =
-//             serde_json::from_str(&apps_json).expect("Error loading the apps.json file");
+/// Checks the page against the embedded apps.json
+pub async fn check(raw_data: Arc<RawData>) -> Detections {
+    EMBEDDED.check(raw_data).await
+}
 
-//         for (app_name, app) in apps_json_data.apps.iter_mut() {
-//             (*app).name = String::from(app_name);
-//         }
+/// A set of apps, ready to check pages: the apps and their compiled rules. It is cheap to
+/// clone, and engines with different apps can be used side by side.
+#[derive(Debug, Clone)]
+pub struct Engine {
+    apps_json_data: Arc<AppsJsonData>,
+    ruleset: Arc<CompiledRuleset>,
+    /// Set to run the pages' scripts, within these limits (see `with_js_engine`)
+    #[cfg(feature = "js-engine")]
+    script_limits: Option<ScriptLimits>,
+}
 
-//         apps_json_data
-//     };
-// }
+impl Engine {
+    pub fn new(apps_json_data: AppsJsonData) -> Engine {
+        let ruleset = CompiledRuleset::compile(&apps_json_data);
+        Engine {
+            apps_json_data: Arc::new(apps_json_data),
+            ruleset: Arc::new(ruleset),
+            #[cfg(feature = "js-engine")]
+            script_limits: None,
+        }
+    }
 
-lazy_static! {
-    static ref APPS_JSON_DATA: AppsJsonData = {
-        let mut apps_json_data: AppsJsonData =
-            serde_json::from_str(APPS_JSON_TEXT).expect("Error loading the apps.json file");
+    /// The engine for the apps.json embedded at build time
+    pub fn embedded() -> Engine {
+        EMBEDDED.clone()
+    }
+
+    /// Loads an apps.json
+    pub fn from_reader<R: Read>(reader: R) -> Result<Engine, WappError> {
+        Ok(Engine::new(AppsJsonData::from_reader(reader)?))
+    }
+
+    /// Loads an apps.json file
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Engine, WappError> {
+        Ok(Engine::new(AppsJsonData::from_path(path)?))
+    }
+
+    pub fn apps_json_data(&self) -> &AppsJsonData {
+        &self.apps_json_data
+    }
+
+    pub fn ruleset(&self) -> &CompiledRuleset {
+        &self.ruleset
+    }
+
+    /// The same apps, compiled without the html set (see `CompiledRuleset::without_html_prefilter`)
+    pub fn without_html_prefilter(self) -> Engine {
+        let ruleset = CompiledRuleset::compile(&self.apps_json_data).without_html_prefilter();
+        Engine {
+            ruleset: Arc::new(ruleset),
+            ..self
+        }
+    }
+
+    /// The same apps, for which the pages' scripts are run in an embedded JS engine within the
+    /// limits, and the js rules matched against the globals that they define (see `js_engine`)
+    #[cfg(feature = "js-engine")]
+    pub fn with_js_engine(self, limits: ScriptLimits) -> Engine {
+        Engine {
+            script_limits: Some(limits),
+            ..self
+        }
+    }
+
+    /// Adds to the page what the rules need beyond its text: the elements that the dom rules
+    /// select, and the globals that its scripts define when the engine is set to run them
+    pub fn prepare(&self, raw_data: Arc<RawData>) -> Arc<RawData> {
+        let raw_data = self
+            .ruleset
+            .with_dom(raw_data, self.ruleset.dom_selectors.keys());
+        self.run_scripts(raw_data)
+    }
+
+    /// Runs the page's scripts when the engine is set to (see `with_js_engine`), adding the
+    /// globals they define to those found in their text. If they can't be run, or otherwise,
+    /// the page is returned as is.
+    fn run_scripts(&self, raw_data: Arc<RawData>) -> Arc<RawData> {
+        #[cfg(feature = "js-engine")]
+        if let Some(limits) = &self.script_limits {
+            if let Ok(globals) = js_engine::run_scripts(&raw_data, self.ruleset.js_paths(), limits)
+            {
+                let mut raw_data = RawData::clone(&raw_data);
+                for (path, global) in globals {
+                    match raw_data.js_globals.get_mut(&path) {
+                        // where the text defines it, which the engine doesn't tell
+                        Some(found) => found.value = global.value,
+                        None => {
+                            raw_data.js_globals.insert(path, global);
+                        }
+                    }
+                }
+                return Arc::new(raw_data);
+            }
+        }
+        raw_data
+    }
+
+    /// Checks the page against every app, each in its own task. The page is prepared on a
+    /// blocking thread, as parsing it and running its scripts would hold up the runtime.
+    pub async fn check(&self, raw_data: Arc<RawData>) -> Detections {
+        let mut futures: Vec<tokio::task::JoinHandle<Option<Tech>>> = vec![];
+        let engine = self.clone();
+        let page = raw_data.clone();
+        let raw_data = tokio::task::spawn_blocking(move || engine.prepare(page))
+            .await
+            .unwrap_or(raw_data);
 
-        for (app_name, app) in apps_json_data.apps.iter_mut() {
-            (*app).name = String::from(app_name);
+        let prefilter = Arc::new(self.ruleset.prefilter(&raw_data));
+        for name in prefilter.apps.iter() {
+            let engine = self.clone();
+            let name = name.clone();
+            let raw_data = raw_data.clone();
+            let prefilter = prefilter.clone();
+            futures.push(tokio::spawn(async move {
+                let app = engine.apps_json_data.apps.get(&name)?;
+                app.check(&engine.ruleset, raw_data, &prefilter)
+            }));
         }
 
-        apps_json_data
+        let detected = join_all(futures)
+            .await
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .filter(|o| o.is_some())
+            .map(|r| r.as_ref().unwrap().to_owned())
+            .collect::<Vec<_>>();
+
+        self.resolve(detected)
+    }
+
+    /// Like `check`, but runs the apps one after the other on the current thread
+    pub fn check_sync(&self, raw_data: Arc<RawData>) -> Detections {
+        let raw_data = self.prepare(raw_data);
+        let prefilter = self.ruleset.prefilter(&raw_data);
+        let detected = prefilter
+            .apps
+            .iter()
+            .filter_map(|name| self.apps_json_data.apps.get(name))
+            .filter_map(|app| app.check(&self.ruleset, raw_data.clone(), &prefilter))
+            .collect::<Vec<_>>();
+
+        self.resolve(detected)
+    }
+
+    /// Applies the `requires` and `implies`, then the `excludes` of the detected apps. A tech,
+    /// detected or implied, without what it requires is dropped, along with what it implies,
+    /// which can leave other techs without what they require: this goes on until every tech
+    /// has what it requires. What is implied only by excluded techs goes with them.
+    fn resolve(&self, detected: Vec<Tech>) -> Detections {
+        let apps_json_data = &self.apps_json_data;
+        // the techs without what they require, and the excluded ones
+        let mut dropped = HashSet::new();
+        let mut excluded = None;
+        loop {
+            let techs = apps_json_data.apply_implies(detected.clone(), &dropped);
+            let lacking = techs
+                .iter()
+                .filter(|tech| !apps_json_data.has_requirements(&tech.name, &techs))
+                .map(|tech| tech.name.clone())
+                .collect::<Vec<_>>();
+            if !lacking.is_empty() {
+                dropped.extend(lacking);
+                continue;
+            }
+
+            match excluded {
+                None => {
+                    let exclusions = apps_json_data.apply_excludes(techs).excluded;
+                    dropped.extend(exclusions.iter().map(|e| e.name.clone()));
+                    excluded = Some(exclusions);
+                }
+                Some(excluded) => {
+                    let mut techs = techs;
+                    techs.sort_by(by_confidence);
+                    return Detections { techs, excluded };
+                }
+            }
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::embedded()
+    }
+}
+
+impl FromStr for Engine {
+    type Err = WappError;
+
+    /// Loads an apps.json from a string
+    fn from_str(apps_json: &str) -> Result<Engine, WappError> {
+        Ok(Engine::new(apps_json.parse()?))
+    }
+}
+
+pub(crate) fn open<P: AsRef<Path>>(path: P) -> Result<BufReader<File>, WappError> {
+    let file = File::open(&path).map_err(|err| {
+        WappError::Ruleset(format!(
+            "Error opening {}: {}",
+            path.as_ref().display(),
+            err
+        ))
+    })?;
+    Ok(BufReader::new(file))
+}
+
+/// Reads the apps and the categories of a directory in the technologies/*.json format (see
+/// `AppsJsonData::from_dir`), as apps and categories or as plain JSON
+pub(crate) fn read_technologies_dir<A, C>(dir: &Path) -> Result<(HashMap<String, A>, C), WappError>
+where
+    A: de::DeserializeOwned,
+    C: de::DeserializeOwned,
+{
+    let read_error = |path: &Path, err: &dyn fmt::Display| {
+        WappError::Ruleset(format!("Error loading {}: {}", path.display(), err))
     };
+
+    let categories_path = dir.join("categories.json");
+    let categories = serde_json::from_reader(open(&categories_path)?)
+        .map_err(|err| read_error(&categories_path, &err))?;
+
+    let technologies_dir = dir.join("technologies");
+    let mut paths = technologies_dir
+        .read_dir()
+        .map_err(|err| read_error(&technologies_dir, &err))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut apps = HashMap::new();
+    for path in paths {
+        let file_apps: HashMap<String, A> =
+            serde_json::from_reader(open(&path)?).map_err(|err| read_error(&path, &err))?;
+        for (name, app) in file_apps {
+            if apps.insert(name.clone(), app).is_some() {
+                return Err(read_error(&path, &format!("'{}' is defined twice", name)));
+            }
+        }
+    }
+
+    Ok((apps, categories))
+}
+
+fn load_error(err: serde_json::Error) -> WappError {
+    WappError::Ruleset(format!("Error loading the apps.json: {}", err))
+}
+
+lazy_static! {
+    static ref EMBEDDED: Engine = APPS_JSON_TEXT
+        .parse()
+        .expect("Error loading the apps.json file");
+}
+
+/// The embedded apps.json
+pub fn apps_json_data() -> &'static AppsJsonData {
+    &EMBEDDED.apps_json_data
+}
+
+/// The rules of the embedded apps.json, compiled
+pub fn compiled_ruleset() -> &'static CompiledRuleset {
+    &EMBEDDED.ruleset
 }
 
 /// A technology that is found on a page
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Tech {
+    /// The name of the most relevant category, the first of `categories`
     pub category: String,
+    /// Every category of the app, by priority
+    #[serde(default)]
+    pub categories: Vec<TechCategory>,
     pub name: String,
+    /// The version, when one of the matching rules carries a `\;version:` tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The sum of the `\;confidence:` of every matching rule, capped at 100
+    pub confidence: u32,
+    /// The apps whose `implies` brought in this tech
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub implied_by: Vec<String>,
+    /// The rules that matched, and what they matched
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evidence: Vec<Evidence>,
+    /// The CPE 2.3 name of the tech, with the version when it is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpe: Option<String>,
 }
 impl Tech {
-    /// let tech = Tech::named("webpack");
+    /// ```
+    /// # use wappalyzer::wapp::Tech;
+    /// let tech = Tech::named("webpack").unwrap();
     /// assert_eq!(tech.name, "webpack");
     /// assert_eq!(tech.category, "Miscellaneous");
-    // fn named(name: &str) -> Option<Tech> {
-    //     if let Some(app) = APPS_JSON_DATA.named(name) {
-    //         Some(Tech::from(app))
-    //     } else {
-    //         None
-    //     }
-    // }
+    /// ```
+    pub fn named(name: &str) -> Option<Tech> {
+        apps_json_data().named(name).map(Tech::from)
+    }
 
     pub fn from(app: &App) -> Tech {
         Tech {
             name: app.name.clone(),
             category: app.category_name(),
+            categories: app.categories(),
+            version: None,
+            confidence: MAX_CONFIDENCE,
+            implied_by: vec![],
+            evidence: vec![],
+            cpe: app.cpe_name(None),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppsJsonData {
+    /// `technologies` in the single-file version of the technologies/*.json format
+    #[serde(alias = "technologies")]
     apps: HashMap<String, App>,
     categories: HashMap<u32, Category>,
 }
 impl AppsJsonData {
-    // fn named(&self, name: &str) -> Option<&App> {
-    //     self.apps.get(&String::from(name))
-    // }
+    fn parse(apps_json: &str) -> serde_json::Result<AppsJsonData> {
+        let apps_json_data: AppsJsonData = serde_json::from_str(apps_json)?;
+        Ok(apps_json_data.resolved())
+    }
+
+    /// The apps.json embedded at build time
+    pub fn embedded() -> AppsJsonData {
+        apps_json_data().clone()
+    }
+
+    /// Loads an apps.json
+    pub fn from_reader<R: Read>(reader: R) -> Result<AppsJsonData, WappError> {
+        let apps_json_data: AppsJsonData = serde_json::from_reader(reader).map_err(load_error)?;
+        Ok(apps_json_data.resolved())
+    }
+
+    /// Loads an apps.json file, or a directory in the technologies/*.json format (see
+    /// `from_dir`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<AppsJsonData, WappError> {
+        if path.as_ref().is_dir() {
+            AppsJsonData::from_dir(path)
+        } else {
+            AppsJsonData::from_reader(open(path)?)
+        }
+    }
+
+    /// Loads the apps from a directory in the format the Wappalyzer project moved to from
+    /// apps.json: a `categories.json` file, and the apps split into `technologies/a.json` ...
+    /// `technologies/_.json`, each an object of apps by name
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<AppsJsonData, WappError> {
+        let (apps, categories) = read_technologies_dir(dir.as_ref())?;
+        Ok(AppsJsonData { apps, categories }.resolved())
+    }
+
+    /// Loads the apps from an apps.json, already parsed
+    pub(crate) fn from_value(apps_json: serde_json::Value) -> serde_json::Result<AppsJsonData> {
+        let apps_json_data: AppsJsonData = serde_json::from_value(apps_json)?;
+        Ok(apps_json_data.resolved())
+    }
+
+    /// Layers the overlays over these apps, in order: each overlay's categories are added
+    /// (replacing those with the same id), its apps are added (replacing those with the same
+    /// name), then the apps it disables are removed. Returns the merged apps, along with
+    /// what's worth a look: replaced apps and categories, disabled apps that don't exist, and
+    /// apps left in categories that don't exist.
+    pub fn with_overlays(self, overlays: Vec<Overlay>) -> (AppsJsonData, Vec<OverlayWarning>) {
+        let mut merged = self;
+        let mut warnings = vec![];
+
+        for overlay in overlays {
+            // in a stable order, so that the warnings are too
+            let mut categories = overlay.categories.into_iter().collect::<Vec<_>>();
+            categories.sort_by_key(|(id, _)| *id);
+            let mut apps = overlay.apps.into_iter().collect::<Vec<_>>();
+            apps.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (id, category) in categories {
+                if let Some(replaced) = merged.categories.get(&id) {
+                    if replaced.name != category.name {
+                        warnings.push(OverlayWarning::CategoryReplaced {
+                            id,
+                            name: replaced.name.clone(),
+                            by: category.name.clone(),
+                        });
+                    }
+                }
+                merged.categories.insert(id, category);
+            }
+            for (name, app) in apps {
+                if merged.apps.contains_key(&name) {
+                    warnings.push(OverlayWarning::AppReplaced { app: name.clone() });
+                }
+                merged.apps.insert(name, app);
+            }
+            for name in overlay.disable {
+                if merged.apps.remove(&name).is_none() {
+                    warnings.push(OverlayWarning::UnknownDisabledApp { app: name });
+                }
+            }
+        }
+
+        let mut dangling = merged
+            .apps
+            .iter()
+            .flat_map(|(name, app)| app.cats.iter().map(move |id| (name, id)))
+            .filter(|(_, id)| !merged.categories.contains_key(id))
+            .map(|(name, id)| OverlayWarning::UnknownCategory {
+                app: name.clone(),
+                id: *id,
+            })
+            .collect::<Vec<_>>();
+        dangling.sort_by_key(|warning| warning.to_string());
+        warnings.extend(dangling);
+
+        (merged.resolved(), warnings)
+    }
+
+    /// Fills in what apps.json leaves implicit: the name of each app (the key it's under)
+    /// and its categories
+    fn resolved(mut self) -> AppsJsonData {
+        let categories = &self.categories;
+        for (app_name, app) in self.apps.iter_mut() {
+            app.name = String::from(app_name);
+
+            // most relevant first: by priority (1 being the highest), then in the order of `cats`
+            app.categories = app
+                .cats
+                .iter()
+                .filter_map(|id| {
+                    categories.get(id).map(|category| TechCategory {
+                        id: *id,
+                        name: category.name.clone(),
+                        priority: category.priority,
+                    })
+                })
+                .collect();
+            app.categories.sort_by_key(|category| category.priority);
+        }
+        self
+    }
+
+    /// Adds the apps implied by the detected ones, recursively. Each app's `implies` is only
+    /// followed once, so cycles (A implies B implies A) terminate. An implied tech gets the
+    /// confidence of each tech implying it, scaled by the confidence tag of the `implies`,
+    /// once the confidences of those have settled. The dropped techs are neither kept nor
+    /// implied.
+    fn apply_implies(&self, mut techs: Vec<Tech>, dropped: &HashSet<String>) -> Vec<Tech> {
+        techs.retain(|tech| !dropped.contains(&tech.name));
+        let detected = techs
+            .iter()
+            .map(|t| (t.name.clone(), t.confidence))
+            .collect::<HashMap<_, _>>();
+        let mut to_visit = techs.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        let mut visited = HashSet::new();
 
-    fn category_name(&self, id: u32) -> Option<String> {
-        match self.categories.get(&id) {
-            // Some(category) => Some(String::from(category.name)),
-            Some(category) => Some(category.name.clone()),
-            None => None,
+        while let Some(name) = to_visit.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            let app = match self.apps.get(&name) {
+                Some(app) => app,
+                None => continue,
+            };
+
+            for implies in app.implies.iter() {
+                // ex: "PHP\;confidence:50"
+                let implied_app = match self.apps.get(&Pattern::parse(implies).app_name()) {
+                    Some(implied_app) if !dropped.contains(&implied_app.name) => implied_app,
+                    _ => continue,
+                };
+                if let Some(tech) = techs.iter_mut().find(|t| t.name == implied_app.name) {
+                    if !tech.implied_by.contains(&name) {
+                        tech.implied_by.push(name.clone());
+                    }
+                } else {
+                    techs.push(Tech {
+                        confidence: 0,
+                        implied_by: vec![name.clone()],
+                        ..Tech::from(implied_app)
+                    });
+                }
+                to_visit.push(implied_app.name.clone());
+            }
+        }
+
+        // the confidences only go up, and are capped: this settles whatever the order
+        loop {
+            let mut changed = false;
+            for i in 0..techs.len() {
+                let implied = techs[i]
+                    .implied_by
+                    .iter()
+                    .map(|source| self.implied_confidence(source, &techs[i].name, &techs))
+                    .sum::<u32>();
+                let own = detected.get(&techs[i].name).copied().unwrap_or(0);
+                let confidence = (own + implied).min(MAX_CONFIDENCE);
+                if confidence != techs[i].confidence {
+                    techs[i].confidence = confidence;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return techs;
+            }
+        }
+    }
+
+    /// The confidence that the source tech passes on to a tech it implies
+    fn implied_confidence(&self, source: &str, name: &str, techs: &[Tech]) -> u32 {
+        let source_confidence = match techs.iter().find(|t| t.name == source) {
+            Some(tech) => tech.confidence,
+            None => return 0,
+        };
+        let implies = self.apps.get(source).map(|app| app.implies.as_slice());
+        implies
+            .unwrap_or_default()
+            .iter()
+            .map(|implies| Pattern::parse(implies))
+            .filter(|pattern| pattern.app_name() == name)
+            .map(|pattern| source_confidence * pattern.confidence / MAX_CONFIDENCE)
+            .sum()
+    }
+
+    /// Whether the techs include one of the apps that the app `requires`, and an app in one of
+    /// the categories that it requires, when it has such rules
+    fn has_requirements(&self, name: &str, techs: &[Tech]) -> bool {
+        let app = match self.apps.get(name) {
+            Some(app) => app,
+            None => return true,
+        };
+        let others = || techs.iter().filter(|tech| tech.name != name);
+
+        let requires = app.requires.is_empty()
+            || app.requires.iter().any(|required| {
+                let required = Pattern::parse(required).app_name();
+                others().any(|tech| tech.name == required)
+            });
+        let requires_category = app.requires_category.is_empty()
+            || app.requires_category.iter().any(|id| {
+                others().any(|tech| tech.categories.iter().any(|category| category.id == *id))
+            });
+        requires && requires_category
+    }
+
+    /// Drops the techs excluded by other detected techs. To keep the outcome deterministic
+    /// when two apps exclude each other, the techs are considered by decreasing confidence
+    /// then by name, and a tech that has already been excluded doesn't exclude others.
+    fn apply_excludes(&self, mut techs: Vec<Tech>) -> Detections {
+        techs.sort_by(by_confidence);
+
+        let mut excluded: Vec<Exclusion> = vec![];
+        for tech in techs.iter() {
+            if excluded.iter().any(|e| e.name == tech.name) {
+                continue;
+            }
+            let app = match self.apps.get(&tech.name) {
+                Some(app) => app,
+                None => continue,
+            };
+            for excludes in app.excludes.iter() {
+                let name = Pattern::parse(excludes).app_name();
+                if techs.iter().any(|t| t.name == name) && !excluded.iter().any(|e| e.name == name)
+                {
+                    excluded.push(Exclusion {
+                        name,
+                        excluded_by: tech.name.clone(),
+                    });
+                }
+            }
         }
+
+        techs.retain(|t| !excluded.iter().any(|e| e.name == t.name));
+        Detections { techs, excluded }
+    }
+
+    /// Looks up an app by its name, ex: "WordPress"
+    pub fn named(&self, name: &str) -> Option<&App> {
+        self.apps.get(name)
+    }
+
+    /// Every app, sorted by name
+    pub fn apps(&self) -> Vec<&App> {
+        let mut apps = self.apps.values().collect::<Vec<_>>();
+        apps.sort_by(|a, b| a.name.cmp(&b.name));
+        apps
+    }
+
+    /// The apps listed in a category, sorted by name
+    pub fn apps_in_category(&self, id: u32) -> Vec<&App> {
+        self.apps()
+            .into_iter()
+            .filter(|app| app.cats.contains(&id))
+            .collect()
+    }
+
+    /// Every category, sorted by id
+    pub fn categories(&self) -> Vec<TechCategory> {
+        let mut ids = self.categories.keys().collect::<Vec<_>>();
+        ids.sort();
+        ids.into_iter()
+            .filter_map(|id| self.category(*id))
+            .collect()
+    }
+
+    pub fn category(&self, id: u32) -> Option<TechCategory> {
+        self.categories.get(&id).map(|category| TechCategory {
+            id,
+            name: category.name.clone(),
+            priority: category.priority,
+        })
     }
 }
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct App {
     #[serde(skip)]
     name: String,
+    #[serde(skip)]
+    categories: Vec<TechCategory>,
     cats: Vec<u32>,
     website: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
     #[serde(default)]
     priority: i32,
     #[serde(deserialize_with = "one_or_more_strings")]
@@ -137,174 +976,1575 @@ pub struct App {
     #[serde(default)]
     js: HashMap<String, String>,
     #[serde(default)]
-    url: String,
+    #[serde(deserialize_with = "one_or_more_strings")]
+    url: Vec<String>,
     #[serde(default)]
-    meta: HashMap<String, String>,
+    #[serde(deserialize_with = "one_or_more_strings_by_key")]
+    meta: HashMap<String, Vec<String>>,
     #[serde(default)]
     icon: String,
+    #[serde(default)]
+    cpe: Option<String>,
     #[serde(deserialize_with = "one_or_more_strings")]
     #[serde(default)]
     implies: Vec<String>,
     #[serde(default)]
     #[serde(deserialize_with = "one_or_more_strings")]
     excludes: Vec<String>,
-    #[serde(default)]
+    /// The `src` of the script tags, `scriptSrc` in the technologies/*.json format
+    #[serde(default, alias = "scriptSrc")]
     #[serde(deserialize_with = "one_or_more_strings")]
     script: Vec<String>,
+    /// The content of the scripts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings")]
+    scripts: Vec<String>,
+    /// The text of the page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings")]
+    text: Vec<String>,
+    /// The page's stylesheets
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings")]
+    css: Vec<String>,
+    /// Apps that must be detected for this one to be, any of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings")]
+    requires: Vec<String>,
+    /// Categories that a detected app must be in for this one to be, any of them
+    #[serde(
+        default,
+        rename = "requiresCategory",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[serde(deserialize_with = "one_or_more")]
+    requires_category: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    saas: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    oss: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pricing: Vec<String>,
+
+    /// CSS selectors, and what to check on the elements they select (see `App::dom_rules`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dom: Option<Value>,
+
+    // The rules below need a browser or more requests than the page: they are kept, but not
+    // checked (see `App::unchecked_fields`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings")]
+    robots: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings")]
+    xhr: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings_by_key")]
+    dns: HashMap<String, Vec<String>>,
+    #[serde(default, rename = "certIssuer", skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "one_or_more_strings")]
+    cert_issuer: Vec<String>,
 }
 
 impl App {
-    pub fn category_name(&self) -> String {
-        assert!(!self.cats.is_empty());
-        APPS_JSON_DATA.category_name(self.cats[0]).unwrap()
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
-    // pub fn check_headers(&self,)
-    // pub async fn tech(
-    //     &self,
-    //     headers: &reqwest::header::HeaderMap,
-    //     cookies: &[crate::Cookie],
-    //     meta_tags: &HashMap<String, String>,
-    //     parsed_html: &Html,
-    //     html: &str,
-    // ) -> Option<Tech> {
-    //     if self.check(headers.clone(), cookies.clone(), meta_tags.clone(), html.clone()) {
-    //         Some(Tech::from(self))
-    //     } else {
-    //         None
-    //     }
-    // }
+    pub fn website(&self) -> &str {
+        &self.website
+    }
 
-    pub fn tech_tokio(
-        &'static self,
-        raw_data: Arc<RawData>,
-    ) -> tokio::task::JoinHandle<Option<Tech>> {
-        tokio::spawn(async move {
-            if self.check(raw_data.clone()) {
-                Some(Tech::from(self))
-            } else {
-                None
-            }
-        })
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
     }
 
-    // TODO: initially only checking for one positive
-    pub fn check(&self, raw_data: Arc<RawData>) -> bool {
-        // check headers
-        for (header_to_check, expected_value) in self.headers.iter() {
-            if let Some(value) = raw_data.headers.get(header_to_check) {
-                // println!("1. {:?}", value);
-                if let Ok(string_value) = value.to_str() {
-                    if check_text(expected_value, string_value) {
-                        // eprintln!(
-                        //     "||| HEADER ({}) hit on: {}",
-                        //     header_to_check, expected_value
-                        // );
-                        return true; // TODO: temp impletation that returns on any hit
-                    }
-                }
-            }
-        }
+    /// Whether the app is sold as a service, when known
+    pub fn saas(&self) -> Option<bool> {
+        self.saas
+    }
 
-        // html
-        for maybe_regex in self.html.iter() {
-            if check_text(maybe_regex, &raw_data.html) {
-                // eprintln!("||| HTML hit on: {}", maybe_regex);
-                return true; // TODO: temp impletation that returns on any hit
-            }
-        }
+    /// Whether the app is open source, when known
+    pub fn oss(&self) -> Option<bool> {
+        self.oss
+    }
 
-        // cookies
-        for (cookies_to_check, expected_value) in self.cookies.iter() {
-            // Examples from app.json
-            // "__cfduid": ""
-            // "__derak_auth": "",
-            // "_session_id": "\\;confidence:75"
-            // "ci_csrf_token": "^(.+)$\\;version:\\1?2+:",
-            // "Fe26.2**": "\\;confidence:50"
+    /// The pricing plans of the app, ex: ["freemium", "recurring"]
+    pub fn pricing(&self) -> &[String] {
+        &self.pricing
+    }
 
-            // COOKIE: Cookie { cookie_string: Some("1P_JAR=2019-09-18-19; expires=Fri, 18-Oct-2019 19:05:14 GMT; path=/; domain=.google.com; SameSite=none"), name: Indexed(0, 6), value: Indexed(7, 20), expires: Some(Tm { tm_sec: 14, tm_min: 5, tm_hour: 19, tm_mday: 18, tm_mon: 9, tm_year: 119, tm_wday: 5, tm_yday: 0, tm_isdst: 0, tm_utcoff: 0, tm_nsec: 0 }), max_age: None, domain: Some(Indexed(77, 87)), path: Some(Indexed(66, 67)), secure: None, http_only: None, same_site: None }
-            // COOKIE: Cookie { cookie_string: Some("NID=188=E7jfAOxVZYeABbEwAi-4RN6pK1a-98zWM1hcFnt8bjHM_303Gon7qmJCopif_taWAwwNrpB9bcjQXn1Mm9gRzIagJSoLll4Wp0XHrPtBUMIXN58jCbdQFVEKAz1yJgyi_oxdG6NVYB2An8_RWmJ-EWp-6umHMMatZfxTAyE2-n8; expires=Thu, 19-Mar-2020 19:05:14 GMT; path=/; domain=.google.com; HttpOnly"), name: Indexed(0, 3), value: Indexed(4, 179), expires: Some(Tm { tm_sec: 14, tm_min: 5, tm_hour: 19, tm_mday: 19, tm_mon: 2, tm_year: 120, tm_wday: 4, tm_yday: 0, tm_isdst: 0, tm_utcoff: 0, tm_nsec: 0 }), max_age: None, domain: Some(Indexed(236, 246)), path: Some(Indexed(225, 226)), secure: None, http_only: Some(true), same_site: None }
+    /// The ids of the app's categories, as in apps.json
+    pub(crate) fn cats(&self) -> &[u32] {
+        &self.cats
+    }
 
-            // loop through and find the appropriate cookie
-            if let Some(c) = raw_data.cookies.iter().find(|c| {
-                // eprintln!("COOKIE: ({})==({})", c.name(), cookies_to_check);
+    /// The names of the js globals the app has rules for
+    pub(crate) fn js_names(&self) -> Vec<&String> {
+        self.js.keys().collect()
+    }
+
+    /// The `implies`, `excludes` and `requires` of the app, as (field, names): the names
+    /// of other apps, with tags
+    pub(crate) fn references(&self) -> [(&'static str, &[String]); 3] {
+        [
+            ("implies", &self.implies),
+            ("excludes", &self.excludes),
+            ("requires", &self.requires),
+        ]
+    }
+
+    /// The ids of the categories the app requires an app from
+    pub(crate) fn requires_category(&self) -> &[u32] {
+        &self.requires_category
+    }
+
+    /// Every rule value of the app that holds a regex, as (field, key, value), the key being the
+    /// header, cookie, meta or js name, or the dom selector, for the fields that have one
+    pub(crate) fn rules(&self) -> Vec<(&'static str, Option<&String>, &String)> {
+        let mut rules = vec![];
+        for (field, values) in [("headers", &self.headers), ("cookies", &self.cookies)].iter() {
+            rules.extend(values.iter().map(|(k, v)| (*field, Some(k), v)));
+        }
+        rules.extend(self.js.iter().map(|(k, v)| ("js", Some(k), v)));
+        rules.extend(
+            self.dom_rules()
+                .into_iter()
+                .map(|(k, _, v)| ("dom", Some(k), v)),
+        );
+        rules.extend(
+            self.meta
+                .iter()
+                .flat_map(|(k, values)| values.iter().map(move |v| ("meta", Some(k), v))),
+        );
+        let lists = [
+            ("html", &self.html),
+            ("script", &self.script),
+            ("scripts", &self.scripts),
+            ("text", &self.text),
+            ("css", &self.css),
+            ("url", &self.url),
+        ];
+        for (field, values) in lists.iter() {
+            rules.extend(values.iter().map(|v| (*field, None, v)));
+        }
+        rules
+    }
+
+    /// The `dom` rules of the app, as (selector, check, value), in any of the shapes of apps.json:
+    /// a selector or a list of selectors, whose elements only need to exist, or selectors to
+    /// their checks, ex: `{ "#app": { "attributes": { "data-version": "(.+)\;version:\1" } } }`.
+    /// What isn't in one of these shapes is left out.
+    pub(crate) fn dom_rules(&self) -> Vec<(&String, DomCheck, &String)> {
+        static EXISTS: String = String::new();
+        let mut rules = vec![];
+        match &self.dom {
+            Some(Value::String(selector)) => rules.push((selector, DomCheck::Exists, &EXISTS)),
+            Some(Value::Array(selectors)) => {
+                for selector in selectors {
+                    if let Value::String(selector) = selector {
+                        rules.push((selector, DomCheck::Exists, &EXISTS));
+                    }
+                }
+            }
+            Some(Value::Object(selectors)) => {
+                for (selector, checks) in selectors {
+                    let checks = match checks.as_object() {
+                        Some(checks) => checks,
+                        None => continue,
+                    };
+                    for (check, value) in checks {
+                        match (check.as_str(), value) {
+                            ("exists", Value::String(value)) => {
+                                rules.push((selector, DomCheck::Exists, value))
+                            }
+                            ("text", Value::String(value)) => {
+                                rules.push((selector, DomCheck::Text, value))
+                            }
+                            ("attributes", Value::Object(names)) => {
+                                for (name, value) in names {
+                                    if let Value::String(value) = value {
+                                        let check = DomCheck::Attribute(name.clone());
+                                        rules.push((selector, check, value));
+                                    }
+                                }
+                            }
+                            ("properties", Value::Object(names)) => {
+                                for (name, value) in names {
+                                    if let Value::String(value) = value {
+                                        let check = DomCheck::Property(name.clone());
+                                        rules.push((selector, check, value));
+                                    }
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+        rules
+    }
+
+    /// The apps that this one implies, with their tags, ex: "PHP\;confidence:50"
+    pub fn implies(&self) -> &[String] {
+        &self.implies
+    }
+
+    /// The apps that this one excludes
+    pub fn excludes(&self) -> &[String] {
+        &self.excludes
+    }
+
+    /// The apps that this one requires, any of them
+    pub fn requires(&self) -> &[String] {
+        &self.requires
+    }
+
+    /// The fields of the app that hold rules this crate doesn't check, as they need a browser
+    /// (the properties of dom elements, xhr) or other requests than the page (robots, dns,
+    /// certIssuer)
+    pub fn unchecked_fields(&self) -> Vec<&'static str> {
+        let mut fields = vec![];
+        let dom_rules = self.dom_rules();
+        if dom_rules
+            .iter()
+            .any(|(_, check, _)| matches!(check, DomCheck::Property(_)))
+        {
+            fields.push("dom");
+        }
+        if !self.robots.is_empty() {
+            fields.push("robots");
+        }
+        if !self.xhr.is_empty() {
+            fields.push("xhr");
+        }
+        if !self.dns.is_empty() {
+            fields.push("dns");
+        }
+        if !self.cert_issuer.is_empty() {
+            fields.push("certIssuer");
+        }
+        fields
+    }
+
+    /// The icon's file name, from the icons directory of the Wappalyzer project
+    pub fn icon(&self) -> Option<&str> {
+        Some(self.icon.as_str()).filter(|icon| !icon.is_empty())
+    }
+
+    /// The CPE (Common Platform Enumeration) name of the app, as given in apps.json,
+    /// ex: "cpe:/a:wordpress:wordpress"
+    pub fn cpe(&self) -> Option<&str> {
+        self.cpe.as_deref()
+    }
+
+    /// The CPE of the app as a CPE 2.3 formatted string, with the version filled in if given
+    pub fn cpe_name(&self, version: Option<&str>) -> Option<String> {
+        let cpe = Cpe::parse(self.cpe.as_ref()?).ok()?;
+        let cpe = match version {
+            Some(version) => cpe.with_version(version),
+            None => cpe,
+        };
+        Some(cpe.to_string())
+    }
+
+    /// The name of the app's most relevant category
+    pub fn category_name(&self) -> String {
+        self.categories()
+            .into_iter()
+            .next()
+            .map(|category| category.name)
+            .unwrap_or_default()
+    }
+
+    /// The categories of the app, most relevant first: by priority (1 being the highest), then
+    /// in the order of `cats`
+    pub fn categories(&self) -> Vec<TechCategory> {
+        self.categories.clone()
+    }
+
+    // pub fn check_headers(&self,)
+    // pub async fn tech(
+    //     &self,
+    //     headers: &reqwest::header::HeaderMap,
+    //     cookies: &[crate::Cookie],
+    //     meta_tags: &HashMap<String, String>,
+    //     parsed_html: &Html,
+    //     html: &str,
+    // ) -> Option<Tech> {
+    //     if self.check(headers.clone(), cookies.clone(), meta_tags.clone(), html.clone()) {
+    //         Some(Tech::from(self))
+    //     } else {
+    //         None
+    //     }
+    // }
+
+    /// Checks the page against the app's rules. The elements its dom rules look at are
+    /// selected here when the page doesn't have them (see `Engine::prepare`).
+    pub fn check(
+        &self,
+        ruleset: &CompiledRuleset,
+        raw_data: Arc<RawData>,
+        prefilter: &Prefilter,
+    ) -> Option<Tech> {
+        let rules = ruleset.apps.get(&self.name)?;
+        let raw_data = ruleset.with_dom(raw_data, rules.dom_selectors());
+        self.detect(rules.matches(&raw_data, prefilter, None))
+    }
+
+    /// Same as `check`, also timing every rule that is run on the page
+    pub fn check_profiled(
+        &self,
+        ruleset: &CompiledRuleset,
+        raw_data: Arc<RawData>,
+        prefilter: &Prefilter,
+    ) -> (Option<Tech>, Vec<RuleTiming>) {
+        let mut timings = vec![];
+        let tech = ruleset.apps.get(&self.name).and_then(|rules| {
+            let raw_data = ruleset.with_dom(raw_data, rules.dom_selectors());
+            self.detect(rules.matches(&raw_data, prefilter, Some(&mut timings)))
+        });
+        (tech, timings)
+    }
+
+    /// The tech detected by the rules that matched, if any did
+    fn detect(&self, matches: Vec<PatternMatch>) -> Option<Tech> {
+        if matches.is_empty() {
+            return None;
+        }
+
+        let evidence = matches.iter().map(|m| m.evidence.clone()).collect();
+
+        // As in the JS library, the confidence of every hit is summed, capped at 100
+        let confidence = matches
+            .iter()
+            .map(|m| m.confidence)
+            .sum::<u32>()
+            .min(MAX_CONFIDENCE);
+
+        // Like the JS library, when several rules carry a version, the longest (most specific) one wins
+        let version = matches.into_iter().filter_map(|m| m.version).fold(
+            None,
+            |longest: Option<String>, v| match longest {
+                Some(l) if l.len() >= v.len() => Some(l),
+                _ => Some(v),
+            },
+        );
+
+        Some(Tech {
+            cpe: self.cpe_name(version.as_deref()),
+            version,
+            confidence,
+            evidence,
+            ..Tech::from(self)
+        })
+    }
+}
+
+/// What a `dom` rule checks on the elements that its selector selects
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum DomCheck {
+    /// That there is one, the value only holding tags
+    Exists,
+    /// Their text
+    Text,
+    /// The value of an attribute
+    Attribute(String),
+    /// The value of a JavaScript property, which needs a browser: never checked
+    Property(String),
+}
+
+/// A rule value that could not be compiled when loading the apps
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PatternError {
+    pub app: String,
+    /// The apps.json field of the rule (headers, html, cookies, js, meta, dom, script, scripts,
+    /// text, css, url)
+    pub field: String,
+    /// The header, cookie, meta or js name, or the dom selector, for the fields that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub pattern: String,
+    pub error: String,
+}
+
+/// Every app's rules with their regexes compiled and their tags parsed, built once from the
+/// apps so that scanning a page doesn't compile anything.
+#[derive(Debug)]
+pub struct CompiledRuleset {
+    apps: HashMap<String, CompiledApp>,
+    /// Header names (lowercased, as in a HeaderMap) to the apps with a rule on that header
+    headers_index: HashMap<String, Vec<String>>,
+    /// Cookie names to the apps with a rule on that cookie
+    cookies_index: HashMap<String, Vec<String>>,
+    /// Meta names to the apps with a rule on that meta tag
+    meta_index: HashMap<String, Vec<String>>,
+    /// Dom selectors to the apps with a rule on the elements they select
+    dom_index: HashMap<String, Vec<String>>,
+    /// Every dom selector (that parses) of every app, to select the elements of a page once
+    dom_selectors: HashMap<String, Selector>,
+    /// The apps with js, script, scripts, text, css or url rules, or with html patterns that
+    /// aren't in the html set, which are always checked
+    content_apps: Vec<String>,
+    /// Every html pattern of every app, as (app name, regex), in the order of `html_set`
+    html_patterns: Vec<(String, String)>,
+    /// All of the html patterns in one set, to find the ones that match a page in a single
+    /// pass over the html. `None` if the set couldn't be built (or was turned off), in which
+    /// case every html pattern is run on its own.
+    html_set: Option<RegexSet>,
+    errors: Vec<PatternError>,
+    /// The patterns that the regex crate couldn't compile (with its error) but fancy-regex could
+    fallbacks: Vec<PatternError>,
+    /// The property paths of every js rule, sorted
+    js_paths: Vec<String>,
+}
+
+/// The apps with patterns that never match, with the regex crate alone (before) and with the
+/// fancy-regex fallback (after)
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FallbackCounts {
+    pub apps_before: usize,
+    pub patterns_before: usize,
+    pub apps_after: usize,
+    pub patterns_after: usize,
+}
+impl fmt::Display for FallbackCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "apps with patterns that never match: {} ({} patterns) with the regex crate, {} ({} patterns) with the fallback",
+            self.apps_before, self.patterns_before, self.apps_after, self.patterns_after
+        )
+    }
+}
+
+/// What the ruleset-wide prefilters found in a page
+#[derive(Debug, Default)]
+pub struct Prefilter {
+    /// The apps with a rule that could match the page
+    apps: HashSet<String>,
+    /// The indexes of the html patterns that match the page, unless there's no html set
+    html_patterns: Option<HashSet<usize>>,
+}
+impl Prefilter {
+    /// The names of the apps to check
+    pub fn apps(&self) -> impl Iterator<Item = &String> {
+        self.apps.iter()
+    }
+
+    /// The same apps to check, with each of their html patterns run on its own rather than
+    /// only those in the html set's matches
+    pub(crate) fn without_html_set(&self) -> Prefilter {
+        Prefilter {
+            apps: self.apps.clone(),
+            html_patterns: None,
+        }
+    }
+}
+impl CompiledRuleset {
+    pub fn compile(apps_json_data: &AppsJsonData) -> CompiledRuleset {
+        let mut ruleset = CompiledRuleset {
+            apps: HashMap::new(),
+            headers_index: HashMap::new(),
+            cookies_index: HashMap::new(),
+            meta_index: HashMap::new(),
+            dom_index: HashMap::new(),
+            dom_selectors: HashMap::new(),
+            content_apps: vec![],
+            html_patterns: vec![],
+            html_set: None,
+            errors: vec![],
+            fallbacks: vec![],
+            js_paths: vec![],
+        };
+
+        for app in apps_json_data.apps.values() {
+            let compiled = CompiledApp::compile(app, &mut ruleset);
+
+            for (header, _) in compiled.headers.iter() {
+                index(&mut ruleset.headers_index, header.to_lowercase(), &app.name);
+            }
+            for (cookie, _) in compiled.cookies.iter() {
+                index(&mut ruleset.cookies_index, cookie.clone(), &app.name);
+            }
+            for (meta, _) in compiled.meta.iter() {
+                index(&mut ruleset.meta_index, meta.clone(), &app.name);
+            }
+            for (selector, _, _) in compiled.dom.iter() {
+                index(&mut ruleset.dom_index, selector.clone(), &app.name);
+            }
+            if !compiled.js.is_empty()
+                || !compiled.script.is_empty()
+                || !compiled.scripts.is_empty()
+                || !compiled.text.is_empty()
+                || !compiled.css.is_empty()
+                || !compiled.url.is_empty()
+                || compiled.html.iter().any(|(i, _)| i.is_none())
+            {
+                ruleset.content_apps.push(app.name.clone());
+            }
+
+            ruleset
+                .js_paths
+                .extend(compiled.js.iter().map(|(path, _)| path.clone()));
+            ruleset.apps.insert(app.name.clone(), compiled);
+        }
+        ruleset.js_paths.sort();
+        ruleset.js_paths.dedup();
+
+        // every pattern compiled on its own, but the set can still go over the size limit
+        ruleset.html_set = RegexSetBuilder::new(ruleset.html_patterns.iter().map(|(_, r)| r))
+            .size_limit(HTML_SET_SIZE_LIMIT)
+            .build()
+            .ok();
+
+        ruleset
+    }
+
+    /// Drops the html set, so that every html pattern is run on its own. Mostly useful to
+    /// measure what the set brings.
+    pub fn without_html_prefilter(mut self) -> CompiledRuleset {
+        self.html_set = None;
+        self
+    }
+
+    /// The patterns that failed to compile, and so will never match
+    pub fn errors(&self) -> &[PatternError] {
+        &self.errors
+    }
+
+    /// The patterns that the regex crate couldn't compile, which are run by fancy-regex
+    pub fn fallbacks(&self) -> &[PatternError] {
+        &self.fallbacks
+    }
+
+    /// The property paths of the js rules, which are read from the pages' globals
+    pub fn js_paths(&self) -> &[String] {
+        &self.js_paths
+    }
+
+    /// Selects the elements of the page for the dom selectors that it wasn't run through yet,
+    /// by selector
+    pub fn select_dom(&self, raw_data: &RawData) -> HashMap<String, Vec<DomElement>> {
+        self.select_missing(raw_data, self.dom_selectors.keys())
+    }
+
+    /// The elements that the selectors select, for those the page doesn't have yet. The page
+    /// is only parsed when there are any.
+    fn select_missing<'s>(
+        &self,
+        raw_data: &RawData,
+        sources: impl Iterator<Item = &'s String>,
+    ) -> HashMap<String, Vec<DomElement>> {
+        let mut selectors = sources
+            .filter(|source| !raw_data.dom.contains_key(*source))
+            .filter_map(|source| self.dom_selectors.get_key_value(source))
+            .peekable();
+        if selectors.peek().is_none() {
+            return HashMap::new();
+        }
+        select(&Html::parse_document(&raw_data.html), selectors)
+    }
+
+    /// The page, with the elements that the selectors select when it doesn't have them yet
+    fn with_dom<'s>(
+        &self,
+        raw_data: Arc<RawData>,
+        sources: impl Iterator<Item = &'s String>,
+    ) -> Arc<RawData> {
+        let dom = self.select_missing(&raw_data, sources);
+        if dom.is_empty() {
+            return raw_data;
+        }
+        let mut raw_data = RawData::clone(&raw_data);
+        raw_data.dom.extend(dom);
+        Arc::new(raw_data)
+    }
+
+    /// How many apps (and patterns) the regex crate alone would leave with patterns that never
+    /// match, and how many are still left with the fallback engine
+    pub fn fallback_counts(&self) -> FallbackCounts {
+        let patterns = |errors: &[&PatternError]| errors.len();
+        let apps = |errors: &[&PatternError]| {
+            errors
+                .iter()
+                .map(|e| e.app.as_str())
+                .collect::<HashSet<_>>()
+                .len()
+        };
+        let after = self.errors.iter().collect::<Vec<_>>();
+        let before = self
+            .fallbacks
+            .iter()
+            .chain(self.errors.iter())
+            .collect::<Vec<_>>();
+        FallbackCounts {
+            apps_before: apps(&before),
+            patterns_before: patterns(&before),
+            apps_after: apps(&after),
+            patterns_after: patterns(&after),
+        }
+    }
+
+    /// Finds the apps that have a rule that could match the page. Apps that only have header,
+    /// cookie, meta or dom rules are skipped unless the page has one of those (or elements that
+    /// the selector selects), and apps that only have html rules are skipped unless one of
+    /// their patterns is in the html set's matches.
+    pub fn prefilter(&self, raw_data: &RawData) -> Prefilter {
+        let mut candidates = self
+            .content_apps
+            .iter()
+            .map(String::as_str)
+            .collect::<HashSet<_>>();
+
+        let html_patterns = self.html_set.as_ref().map(|html_set| {
+            html_set
+                .matches(&raw_data.html)
+                .into_iter()
+                .collect::<HashSet<_>>()
+        });
+        match &html_patterns {
+            Some(html_patterns) => candidates.extend(
+                html_patterns
+                    .iter()
+                    .map(|i| self.html_patterns[*i].0.as_str()),
+            ),
+            None => candidates.extend(self.html_patterns.iter().map(|(app, _)| app.as_str())),
+        }
+
+        let indexed = raw_data
+            .headers
+            .keys()
+            .filter_map(|name| self.headers_index.get(name.as_str()))
+            .chain(
+                raw_data
+                    .cookies
+                    .iter()
+                    .filter_map(|c| self.cookies_index.get(&c.name)),
+            )
+            .chain(
+                raw_data
+                    .meta_tags
+                    .keys()
+                    .filter_map(|name| self.meta_index.get(name)),
+            )
+            .chain(
+                raw_data
+                    .dom
+                    .iter()
+                    .filter(|(_, elements)| !elements.is_empty())
+                    .filter_map(|(selector, _)| self.dom_index.get(selector)),
+            );
+        for apps in indexed {
+            candidates.extend(apps.iter().map(String::as_str));
+        }
+
+        Prefilter {
+            apps: candidates.into_iter().map(String::from).collect(),
+            html_patterns,
+        }
+    }
+}
+
+fn index(index: &mut HashMap<String, Vec<String>>, key: String, app_name: &str) {
+    let apps = index.entry(key).or_default();
+    if !apps.iter().any(|a| a == app_name) {
+        apps.push(String::from(app_name));
+    }
+}
+
+/// The rules of a single app, compiled
+#[derive(Debug)]
+struct CompiledApp {
+    headers: Vec<(String, CompiledPattern)>,
+    /// Along with their index in the ruleset's html set, if they are in it
+    html: Vec<(Option<usize>, CompiledPattern)>,
+    cookies: Vec<(String, CompiledPattern)>,
+    /// By property path, matched against the globals that the page's scripts define
+    js: Vec<(String, CompiledPattern)>,
+    meta: Vec<(String, CompiledPattern)>,
+    /// By selector, matched against the elements it selects. The property checks are left out.
+    dom: Vec<(String, DomCheck, CompiledPattern)>,
+    script: Vec<CompiledPattern>,
+    scripts: Vec<CompiledPattern>,
+    text: Vec<CompiledPattern>,
+    css: Vec<CompiledPattern>,
+    url: Vec<CompiledPattern>,
+}
+impl CompiledApp {
+    /// The selectors of the app's dom rules
+    fn dom_selectors(&self) -> impl Iterator<Item = &String> {
+        self.dom.iter().map(|(selector, _, _)| selector)
+    }
+
+    /// Compiles the rules of the app, recording in the ruleset its html patterns and those
+    /// that failed to compile or needed the fallback engine
+    fn compile(app: &App, ruleset: &mut CompiledRuleset) -> CompiledApp {
+        let mut compile = |field: &str, key: Option<&String>, maybe_regex: &str| {
+            let pattern_error = |error: String| PatternError {
+                app: app.name.clone(),
+                field: String::from(field),
+                key: key.cloned(),
+                pattern: String::from(maybe_regex),
+                error,
+            };
+            match CompiledPattern::compile(maybe_regex) {
+                Ok(pattern) => {
+                    if pattern.regex.is_fallback() {
+                        let err = Regex::new(&Pattern::parse(maybe_regex).regex).err();
+                        let error = err.map(|err| regex_error_summary(&err));
+                        ruleset
+                            .fallbacks
+                            .push(pattern_error(error.unwrap_or_default()));
+                    }
+                    Some(pattern)
+                }
+                Err(err) => {
+                    ruleset.errors.push(pattern_error(err));
+                    None
+                }
+            }
+        };
+
+        let headers = app
+            .headers
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), compile("headers", Some(k), v)?)))
+            .collect();
+        let html = app
+            .html
+            .iter()
+            .filter_map(|v| compile("html", None, v))
+            .collect::<Vec<_>>();
+        let cookies = app
+            .cookies
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), compile("cookies", Some(k), v)?)))
+            .collect();
+        let js = app
+            .js
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), compile("js", Some(k), v)?)))
+            .collect();
+        let meta = app
+            .meta
+            .iter()
+            .flat_map(|(k, values)| values.iter().map(move |v| (k, v)))
+            .filter_map(|(k, v)| Some((k.clone(), compile("meta", Some(k), v)?)))
+            .collect();
+        let dom = app
+            .dom_rules()
+            .into_iter()
+            .filter(|(_, check, _)| !matches!(check, DomCheck::Property(_)))
+            .filter_map(|(k, check, v)| Some((k.clone(), check, compile("dom", Some(k), v)?)))
+            .collect::<Vec<_>>();
+        let mut compile_all = |field: &str, values: &[String]| {
+            values
+                .iter()
+                .filter_map(|v| compile(field, None, v))
+                .collect::<Vec<_>>()
+        };
+        let script = compile_all("script", &app.script);
+        let scripts = compile_all("scripts", &app.scripts);
+        let text = compile_all("text", &app.text);
+        let css = compile_all("css", &app.css);
+        let url = compile_all("url", &app.url);
+
+        // the fallback patterns can't be in the html set, and are always run
+        let html = html
+            .into_iter()
+            .map(|pattern| {
+                if pattern.regex.is_fallback() {
+                    return (None, pattern);
+                }
+                let regex = String::from(pattern.regex.as_str());
+                ruleset.html_patterns.push((app.name.clone(), regex));
+                (Some(ruleset.html_patterns.len() - 1), pattern)
+            })
+            .collect();
+
+        let mut selectors = dom.iter().map(|(k, _, _)| k).collect::<Vec<_>>();
+        selectors.sort();
+        selectors.dedup();
+        for selector in selectors {
+            if ruleset.dom_selectors.contains_key(selector) {
+                continue;
+            }
+            match Selector::parse(selector) {
+                Ok(parsed) => {
+                    ruleset.dom_selectors.insert(selector.clone(), parsed);
+                }
+                Err(_) => ruleset.errors.push(PatternError {
+                    app: app.name.clone(),
+                    field: String::from("dom"),
+                    key: Some(selector.clone()),
+                    pattern: selector.clone(),
+                    error: String::from("not a CSS selector"),
+                }),
+            }
+        }
+
+        CompiledApp {
+            headers,
+            html,
+            cookies,
+            js,
+            meta,
+            dom,
+            script,
+            scripts,
+            text,
+            css,
+            url,
+        }
+    }
+
+    /// Evaluates every rule of the app against the page, returning all of the hits. When
+    /// profiling, every rule that is run is timed into `timings`.
+    fn matches(
+        &self,
+        raw_data: &RawData,
+        prefilter: &Prefilter,
+        mut timings: Option<&mut Vec<RuleTiming>>,
+    ) -> Vec<PatternMatch> {
+        let mut matches = vec![];
+        let timings = &mut timings;
+
+        // check headers
+        for (header_to_check, pattern) in self.headers.iter() {
+            if let Some(value) = raw_data.headers.get(header_to_check) {
+                // println!("1. {:?}", value);
+                if let Ok(string_value) = value.to_str() {
+                    if let Some(m) =
+                        run_rule(timings, "headers", Some(header_to_check), pattern, || {
+                            pattern.matches(string_value, "headers", Some(header_to_check))
+                        })
+                    {
+                        // eprintln!(
+                        //     "||| HEADER ({}) hit on: {}",
+                        //     header_to_check, expected_value
+                        // );
+                        matches.push(m);
+                    }
+                }
+            }
+        }
+
+        // html: only the patterns that the html set found are run again, for the captures
+        for (i, pattern) in self.html.iter() {
+            let m = match (&prefilter.html_patterns, i) {
+                (Some(html_patterns), Some(i)) if !html_patterns.contains(i) => None,
+                _ => run_rule(timings, "html", None, pattern, || {
+                    pattern.matches(&raw_data.html, "html", None)
+                }),
+            };
+            if let Some(m) = m {
+                // eprintln!("||| HTML hit on: {}", maybe_regex);
+                matches.push(m);
+            }
+        }
+
+        // cookies
+        for (cookies_to_check, pattern) in self.cookies.iter() {
+            // Examples from app.json
+            // "__cfduid": ""
+            // "__derak_auth": "",
+            // "_session_id": "\\;confidence:75"
+            // "ci_csrf_token": "^(.+)$\\;version:\\1?2+:",
+            // "Fe26.2**": "\\;confidence:50"
+
+            // loop through and find the appropriate cookie
+            if let Some(c) = raw_data.cookies.iter().find(|c| {
+                // eprintln!("COOKIE: ({})==({})", c.name(), cookies_to_check);
                 c.name == *cookies_to_check
             }) {
-                // an empty expected_value means that we only care about the existence if the cookie
-                if expected_value.is_empty() || check_text(expected_value, &c.value) {
+                // an empty expected_value means that we only care about the existence if the cookie,
+                // which the empty regex takes care of
+                if let Some(m) =
+                    run_rule(timings, "cookies", Some(cookies_to_check), pattern, || {
+                        pattern.matches(&c.value, "cookies", Some(cookies_to_check))
+                    })
+                {
                     // eprintln!("||| COOKIE ({}) hit on: {}", c.value, expected_value);
-                    return true; // TODO: Temp impl where one hit returns
+                    matches.push(m);
+                }
+            }
+        }
+
+        // js: the globals that the inline scripts define. The value pattern (and its version)
+        // applies to a literal value, and a global whose value isn't known only shows that the
+        // app is there.
+        for (path, pattern) in self.js.iter() {
+            if let Some(global) = raw_data.js_globals.get(path) {
+                matches.extend(run_rule(timings, "js", Some(path), pattern, || {
+                    let m = match &global.value {
+                        Some(value) => pattern.matches(value, "js", Some(path))?,
+                        None => PatternMatch {
+                            version: None,
+                            confidence: pattern.confidence,
+                            evidence: Evidence {
+                                field: String::from("js"),
+                                key: Some(path.clone()),
+                                pattern: pattern.source.clone(),
+                                matched: path.clone(),
+                                offset: 0,
+                            },
+                        },
+                    };
+                    Some(PatternMatch {
+                        evidence: Evidence {
+                            offset: global.offset,
+                            ..m.evidence
+                        },
+                        ..m
+                    })
+                }));
+            }
+        }
+
+        // dom: the elements that the selector selected, a check counting once like for script
+        // below. Whether there are any is all that `exists` checks, its value only holding tags.
+        for (selector, check, pattern) in self.dom.iter() {
+            let elements = match raw_data.dom.get(selector) {
+                Some(elements) if !elements.is_empty() => elements,
+                _ => continue,
+            };
+            matches.extend(run_rule(timings, "dom", Some(selector), pattern, || {
+                let key = Some(selector);
+                match check {
+                    DomCheck::Exists => {
+                        let m = pattern.matches("", "dom", key)?;
+                        Some(PatternMatch {
+                            evidence: Evidence {
+                                matched: selector.clone(),
+                                ..m.evidence
+                            },
+                            ..m
+                        })
+                    }
+                    DomCheck::Text => {
+                        pattern.best_match(elements.iter().map(|e| (key, &e.text)), "dom")
+                    }
+                    DomCheck::Attribute(name) => pattern.best_match(
+                        elements
+                            .iter()
+                            .filter_map(|e| Some((key, e.attributes.get(name)?))),
+                        "dom",
+                    ),
+                    DomCheck::Property(_) => None,
+                }
+            }));
+        }
+
+        // script: a pattern can match several srcs (ex: jquery.js and jquery-ui.js), so it
+        // counts once, preferring a hit that carries a version. Same for the scripts' content
+        // and the stylesheets, whether inline or fetched (their hits keyed by their URL).
+        for pattern in self.script.iter() {
+            matches.extend(run_rule(timings, "script", None, pattern, || {
+                let srcs = raw_data.script_srcs.iter().map(|src| (None, src));
+                pattern.best_match(srcs, "script")
+            }));
+        }
+        for pattern in self.scripts.iter() {
+            matches.extend(run_rule(timings, "scripts", None, pattern, || {
+                pattern.best_match(raw_data.scripts(), "scripts")
+            }));
+        }
+        for pattern in self.css.iter() {
+            matches.extend(run_rule(timings, "css", None, pattern, || {
+                pattern.best_match(raw_data.stylesheets(), "css")
+            }));
+        }
+
+        for pattern in self.text.iter() {
+            matches.extend(run_rule(timings, "text", None, pattern, || {
+                pattern.matches(&raw_data.text, "text", None)
+            }));
+        }
+
+        // url: checked against both the requested and the final URL, counting once
+        for pattern in self.url.iter() {
+            let urls = [raw_data.url.clone(), raw_data.final_url.clone()];
+            matches.extend(run_rule(timings, "url", None, pattern, || {
+                pattern.best_match(urls.iter().map(|url| (None, url)), "url")
+            }));
+        }
+
+        // meta
+        for (meta_to_check, pattern) in self.meta.iter() {
+            if let Some(value) = raw_data.meta_tags.get(meta_to_check) {
+                if let Some(m) = run_rule(timings, "meta", Some(meta_to_check), pattern, || {
+                    pattern.matches(value, "meta", Some(meta_to_check))
+                }) {
+                    // eprintln!(
+                    //     "||| META ({}) hit on: {} for value: {}",
+                    //     meta_to_check, expected_value, value
+                    // );
+                    matches.push(m);
+                }
+            }
+        }
+
+        matches
+    }
+}
+
+/// Runs a rule, timing it when profiling
+fn run_rule<F>(
+    timings: &mut Option<&mut Vec<RuleTiming>>,
+    field: &'static str,
+    key: Option<&String>,
+    pattern: &CompiledPattern,
+    run: F,
+) -> Option<PatternMatch>
+where
+    F: FnOnce() -> Option<PatternMatch>,
+{
+    let timings = match timings {
+        Some(timings) => timings,
+        None => return run(),
+    };
+    let start = Instant::now();
+    let m = run();
+    timings.push(RuleTiming {
+        field,
+        key: key.cloned(),
+        pattern: pattern.source.clone(),
+        elapsed: start.elapsed(),
+        matched: m.is_some(),
+    });
+    m
+}
+
+/// A rule that was run on a page while profiling (see `App::check_profiled`)
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuleTiming {
+    /// The apps.json field of the rule (headers, html, cookies, js, meta, dom, script, scripts,
+    /// text, css, url)
+    pub field: &'static str,
+    /// The header, cookie, meta or js name, or the dom selector, for the fields that have one
+    pub key: Option<String>,
+    /// The rule value, with its tags
+    pub pattern: String,
+    pub elapsed: Duration,
+    pub matched: bool,
+}
+
+/// Apps in the apps.json format, to be layered over another set of apps (see
+/// `AppsJsonData::with_overlays`): to add in-house apps, or to replace or disable apps.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Overlay {
+    #[serde(default, alias = "technologies")]
+    apps: HashMap<String, App>,
+    #[serde(default)]
+    categories: HashMap<u32, Category>,
+    /// The names of the apps to remove
+    #[serde(default)]
+    disable: Vec<String>,
+}
+impl Overlay {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Overlay, WappError> {
+        serde_json::from_reader(reader).map_err(load_error)
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Overlay, WappError> {
+        Overlay::from_reader(open(path)?)
+    }
+}
+impl FromStr for Overlay {
+    type Err = WappError;
+
+    fn from_str(overlay: &str) -> Result<Overlay, WappError> {
+        serde_json::from_str(overlay).map_err(load_error)
+    }
+}
+
+impl FromStr for AppsJsonData {
+    type Err = WappError;
+
+    /// Loads an apps.json from a string
+    fn from_str(apps_json: &str) -> Result<AppsJsonData, WappError> {
+        AppsJsonData::parse(apps_json).map_err(load_error)
+    }
+}
+
+/// Something to look at after layering overlays over apps
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum OverlayWarning {
+    /// An overlay replaced the app with the same name
+    AppReplaced { app: String },
+    /// An overlay replaced a category with one that has another name
+    CategoryReplaced { id: u32, name: String, by: String },
+    /// An overlay disables an app that doesn't exist
+    UnknownDisabledApp { app: String },
+    /// An app is in a category that doesn't exist
+    UnknownCategory { app: String, id: u32 },
+}
+impl fmt::Display for OverlayWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlayWarning::AppReplaced { app } => write!(f, "app '{}' was replaced", app),
+            OverlayWarning::CategoryReplaced { id, name, by } => {
+                write!(f, "category {} '{}' was replaced by '{}'", id, name, by)
+            }
+            OverlayWarning::UnknownDisabledApp { app } => {
+                write!(f, "can't disable app '{}', which doesn't exist", app)
+            }
+            OverlayWarning::UnknownCategory { app, id } => {
+                write!(
+                    f,
+                    "app '{}' is in category {}, which doesn't exist",
+                    app, id
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Category {
+    name: String,
+    priority: u8,
+}
+
+/// A category of a detected tech
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TechCategory {
+    pub id: u32,
+    pub name: String,
+    pub priority: u8,
+}
+
+/// A rule value from apps.json, split into the regex and its `\;`-separated tags.
+/// ex: `jquery[.-]([\d.]*\d)[^/]*\.js\;version:\1`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Pattern {
+    pub(crate) regex: String,
+    version: Option<String>,
+    confidence: u32,
+}
+impl Pattern {
+    pub(crate) fn parse(maybe_regex: &str) -> Pattern {
+        let mut parts = maybe_regex.split("\\;");
+        let regex = String::from(parts.next().unwrap_or(""));
+        let mut version = None;
+        let mut confidence = MAX_CONFIDENCE;
+        for tag in parts {
+            if let Some(template) = tag.strip_prefix("version:") {
+                version = Some(String::from(template));
+            } else if let Some(value) = tag.strip_prefix("confidence:") {
+                confidence = value.trim().parse().unwrap_or(MAX_CONFIDENCE);
+            }
+        }
+        Pattern {
+            regex,
+            version,
+            confidence,
+        }
+    }
+
+    /// The app that an `implies` value names, which upstream apps.json writes as a regex:
+    /// without the escapes (ex: `C\+\+` is C++)
+    pub(crate) fn app_name(&self) -> String {
+        let mut name = String::new();
+        let mut chars = self.regex.chars();
+        while let Some(c) = chars.next() {
+            name.push(match c {
+                '\\' => chars.next().unwrap_or(c),
+                _ => c,
+            });
+        }
+        name
+    }
+}
+
+/// What a single pattern hit contributes to a detection
+#[derive(Debug, PartialEq, Clone)]
+struct PatternMatch {
+    version: Option<String>,
+    confidence: u32,
+    evidence: Evidence,
+}
+
+/// The rule behind a detection, and what it matched on the page
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Evidence {
+    /// The apps.json field of the rule (headers, html, cookies, js, meta, dom, script, scripts,
+    /// text, css, url)
+    pub field: String,
+    /// The header, cookie, meta or js name, or the dom selector, for the fields that have one,
+    /// and for scripts and css, the URL of the external script or stylesheet it matched in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// The rule value, with its tags
+    pub pattern: String,
+    pub matched: String,
+    /// The byte offset of `matched` in the text it was found in (the html, the header value, ...),
+    /// or for js, of the definition of the global in its script. For dom, the text is that of
+    /// the element or its attribute, and `matched` the selector when it only has to exist.
+    pub offset: usize,
+}
+
+/// A `Pattern` with its regex compiled
+#[derive(Debug, Clone)]
+struct CompiledPattern {
+    regex: Matcher,
+    version: Option<String>,
+    confidence: u32,
+    /// The rule value it was compiled from
+    source: String,
+}
+impl CompiledPattern {
+    fn compile(maybe_regex: &str) -> Result<CompiledPattern, String> {
+        let pattern = Pattern::parse(maybe_regex);
+        Ok(CompiledPattern {
+            regex: Matcher::compile(&pattern.regex)?,
+            version: pattern.version,
+            confidence: pattern.confidence,
+            source: String::from(maybe_regex),
+        })
+    }
+
+    /// The hit on the first of the texts, or on the first one that gives a version. Each text
+    /// comes with the key of its hit.
+    fn best_match<'t, I>(&self, texts: I, field: &str) -> Option<PatternMatch>
+    where
+        I: IntoIterator<Item = (Option<&'t String>, &'t String)>,
+    {
+        let mut best: Option<PatternMatch> = None;
+        for (key, text) in texts {
+            if let Some(m) = self.matches(text, field, key) {
+                let has_version = m.version.is_some();
+                if best.is_none() || has_version {
+                    best = Some(m);
+                }
+                if has_version {
+                    break;
                 }
             }
         }
+        best
+    }
 
-        // try just checking for the js_to_check value, as (1) the js version seems to use the dom directly, and
-        // (2) the Go version doesn't seem to work
-        for (js_to_check, _rule_value) in self.js.iter() {
-            for js in &raw_data.script_tags {
-                if check_text(js_to_check, js) {
-                    // eprintln!("||| JS hit on: {}", js_to_check);
-                    return true;
-                }
+    // The meat of the matter
+    fn matches(&self, text: &str, field: &str, key: Option<&String>) -> Option<PatternMatch> {
+        let captures = self.regex.captures(text)?;
+        let version = self
+            .version
+            .as_ref()
+            .map(|template| resolve_version(template, &captures))
+            .filter(|version| !version.is_empty());
+        let (offset, found) = captures[0]?;
+        Some(PatternMatch {
+            version,
+            confidence: self.confidence,
+            evidence: Evidence {
+                field: String::from(field),
+                key: key.cloned(),
+                pattern: self.source.clone(),
+                matched: String::from(found),
+                offset,
+            },
+        })
+    }
+}
+
+/// A compiled regex. The patterns are written for JavaScript, and some use what the regex crate
+/// doesn't support (lookarounds, backreferences) or rejects (`[^]`, a `{` that doesn't start a
+/// repetition): those fall back to fancy-regex, which backtracks, within a limit.
+#[derive(Debug, Clone)]
+pub(crate) enum Matcher {
+    Regex(Regex),
+    Fallback(fancy_regex::Regex),
+}
+impl Matcher {
+    /// Compiles with the regex crate, then with fancy-regex. The error is the regex crate's,
+    /// followed by fancy-regex's.
+    pub(crate) fn compile(regex: &str) -> Result<Matcher, String> {
+        let err = match Regex::new(regex) {
+            Ok(regex) => return Ok(Matcher::Regex(regex)),
+            Err(err) => err,
+        };
+        fancy_regex::RegexBuilder::new(&from_javascript(regex))
+            .backtrack_limit(FALLBACK_BACKTRACK_LIMIT)
+            .build()
+            .map(Matcher::Fallback)
+            .map_err(|fallback_err| {
+                format!(
+                    "{} (fancy-regex: {})",
+                    regex_error_summary(&err),
+                    fallback_err
+                )
+            })
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Matcher::Regex(regex) => regex.as_str(),
+            Matcher::Fallback(regex) => regex.as_str(),
+        }
+    }
+
+    pub(crate) fn is_fallback(&self) -> bool {
+        matches!(self, Matcher::Fallback(_))
+    }
+
+    /// The number of groups, counting the whole match as group 0
+    pub(crate) fn captures_len(&self) -> usize {
+        match self {
+            Matcher::Regex(regex) => regex.captures_len(),
+            Matcher::Fallback(regex) => regex.captures_len(),
+        }
+    }
+
+    /// The groups of the first match, as (offset, text), group 0 being the whole match. A
+    /// fallback regex that goes over the backtracking limit doesn't match.
+    fn captures<'t>(&self, text: &'t str) -> Option<Vec<Option<(usize, &'t str)>>> {
+        match self {
+            Matcher::Regex(regex) => {
+                let captures = regex.captures(text)?;
+                Some(
+                    captures
+                        .iter()
+                        .map(|m| m.map(|m| (m.start(), m.as_str())))
+                        .collect(),
+                )
+            }
+            Matcher::Fallback(regex) => {
+                let captures = regex.captures(text).ok()??;
+                Some(
+                    captures
+                        .iter()
+                        .map(|m| m.map(|m| (m.start(), m.as_str())))
+                        .collect(),
+                )
             }
         }
+    }
+}
 
-        // meta
-        for (meta_to_check, expected_value) in self.meta.iter() {
-            if let Some(value) = raw_data.meta_tags.get(meta_to_check) {
-                // an empty expected_value means that we only care about the existence if the cookie
-                if check_text(expected_value, value) {
-                    // eprintln!(
-                    //     "||| META ({}) hit on: {} for value: {}",
-                    //     meta_to_check, expected_value, value
-                    // );
-                    return true; // TODO: Temp impl where one hit returns
+/// Rewrites what JavaScript accepts in a regex but the regex crates reject: `[^]` (any
+/// character) becomes `[\s\S]`, a `{` that doesn't start a repetition is escaped, and the `+`
+/// after a lookaround is dropped (it matches nothing, so repeating it changes nothing)
+fn from_javascript(regex: &str) -> String {
+    let mut rewritten = String::new();
+    let mut in_class = false;
+    // for each open group, whether it's a lookaround
+    let mut groups = vec![];
+    let mut chars = regex.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                rewritten.push(c);
+                rewritten.extend(chars.next().map(|(_, escaped)| escaped));
+            }
+            '[' if !in_class && regex[i..].starts_with("[^]") => {
+                rewritten.push_str("[\\s\\S]");
+                chars.next();
+                chars.next();
+            }
+            '[' if !in_class => {
+                in_class = true;
+                rewritten.push(c);
+                // a `]` right after `[` or `[^` is a literal
+                for literal in ["^", "]"].iter() {
+                    if let Some((_, next)) = chars
+                        .peek()
+                        .filter(|(j, _)| regex[*j..].starts_with(literal))
+                    {
+                        rewritten.push(*next);
+                        chars.next();
+                    }
+                }
+            }
+            ']' if in_class => {
+                in_class = false;
+                rewritten.push(c);
+            }
+            '(' if !in_class => {
+                let lookaround = ["(?=", "(?!", "(?<=", "(?<!"];
+                groups.push(lookaround.iter().any(|l| regex[i..].starts_with(l)));
+                rewritten.push(c);
+            }
+            ')' if !in_class => {
+                rewritten.push(c);
+                if groups.pop() == Some(true) && chars.peek().map(|(_, next)| *next) == Some('+') {
+                    chars.next();
                 }
             }
+            '{' if !in_class && !is_repetition(&regex[i..]) => rewritten.push_str("\\{"),
+            '}' if !in_class && !regex[..i].ends_with(|c: char| c.is_ascii_digit() || c == ',') => {
+                rewritten.push_str("\\}")
+            }
+            _ => rewritten.push(c),
         }
+    }
+    rewritten
+}
+
+/// Whether the text starts with a repetition: `{n}`, `{n,}` or `{n,m}`
+fn is_repetition(text: &str) -> bool {
+    let end = match text.find('}') {
+        Some(end) => end,
+        None => return false,
+    };
+    let mut bounds = text[1..end].splitn(2, ',');
+    let min = bounds.next().unwrap_or("");
+    let max = bounds.next().unwrap_or("");
+    !min.is_empty()
+        && min.chars().all(|c| c.is_ascii_digit())
+        && max.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Fills in a `\;version:` template from the captures of a match, following the JS library:
+/// `\1` is replaced by the first capture group, and `\1?a:b` becomes `a` if the
+/// first group matched and `b` otherwise.
+fn resolve_version(template: &str, captures: &[Option<(usize, &str)>]) -> String {
+    let mut version = String::from(template);
+    for (i, group) in captures.iter().enumerate() {
+        let group = group.map(|(_, group)| group).unwrap_or("");
 
-        // check html
-        false
+        // the ternary runs to the end of the template, like the JS library's `\\1\?([^:]+):(.*)$`
+        let ternary = format!("\\{}?", i);
+        if let Some(start) = version.find(&ternary) {
+            let rest = &version[start + ternary.len()..];
+            if let Some(colon) = rest.find(':').filter(|colon| *colon > 0) {
+                let replacement = if group.is_empty() {
+                    &rest[colon + 1..]
+                } else {
+                    &rest[..colon]
+                };
+                version = format!("{}{}", &version[..start], replacement);
+            }
+        }
+        version = version.trim().replace(&format!("\\{}", i), group);
     }
+    version
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Category {
-    name: String,
-    priority: u8,
+/// The last line of a regex error, the regex crate spreading it over several to point at the
+/// pattern
+fn regex_error_summary(err: &regex::Error) -> String {
+    let message = err.to_string();
+    match message.lines().last() {
+        Some(last) => String::from(last.trim_start_matches("error: ")),
+        None => message,
+    }
 }
 
-// The meat of the matter
-fn check_text(maybe_regex: &str, text: &str) -> bool {
-    // TODO: strignoring version stuff for now.
-    // TODO: Compile regex's in the initialization area.
-    let maybe_regex = String::from(maybe_regex);
-    let maybe_regex = maybe_regex.split("\\;").collect::<Vec<&str>>()[0];
-    match Regex::new(maybe_regex) {
-        Ok(re) => {
-            // println!("REGEX IS FINE: [{}]", maybe_regex);
-            re.is_match(text)
+fn one_or_more_strings<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct StringOrVec(PhantomData<Vec<String>>);
+
+    impl<'de> de::Visitor<'de> for StringOrVec {
+        type Value = Vec<String>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("string or list of strings")
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(vec![value.to_owned()])
+        }
+
+        fn visit_seq<S>(self, visitor: S) -> Result<Self::Value, S::Error>
+        where
+            S: de::SeqAccess<'de>,
+        {
+            Deserialize::deserialize(de::value::SeqAccessDeserializer::new(visitor))
         }
-        Err(_) => {
-            // eprintln!("invalid regex in app.json '{}': {}", maybe_regex, err);
-            // panic!("invalid regex in app.json '{}': {}", maybe_regex, err);
-            false
+    }
+
+    deserializer.deserialize_any(StringOrVec(PhantomData))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMore<T> {
+    One(T),
+    More(Vec<T>),
+}
+impl<T> From<OneOrMore<T>> for Vec<T> {
+    fn from(one_or_more: OneOrMore<T>) -> Vec<T> {
+        match one_or_more {
+            OneOrMore::One(value) => vec![value],
+            OneOrMore::More(values) => values,
         }
     }
 }
 
+fn one_or_more<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    OneOrMore::deserialize(deserializer).map(Vec::from)
+}
+
+/// An object whose values are a string or a list of strings, ex: the `meta` of an app
+pub(crate) fn one_or_more_strings_by_key<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let by_key = HashMap::<String, OneOrMore<String>>::deserialize(deserializer)?;
+    Ok(by_key
+        .into_iter()
+        .map(|(key, values)| (key, Vec::from(values)))
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     // use reqwest::header;
 
-    // #[test]
-    // fn tech_lookup() {
-    //     let tech = Tech::named("webpack").unwrap();
-    //     assert_eq!(tech.name, "webpack");
-    //     assert_eq!(tech.category, "Miscellaneous");
-    // }
+    fn check_text(maybe_regex: &str, text: &str) -> bool {
+        match_text(maybe_regex, text).is_some()
+    }
+
+    fn match_text(maybe_regex: &str, text: &str) -> Option<PatternMatch> {
+        CompiledPattern::compile(maybe_regex)
+            .ok()?
+            .matches(text, "html", None)
+    }
+
+    #[test]
+    fn tech_lookup() {
+        let tech = Tech::named("webpack").unwrap();
+        assert_eq!(tech.name, "webpack");
+        assert_eq!(tech.category, "Miscellaneous");
+        assert_eq!(Tech::named("not an app"), None);
+    }
+
+    #[test]
+    fn catalog() {
+        let wordpress = apps_json_data().named("WordPress").unwrap();
+        assert_eq!(wordpress.name(), "WordPress");
+        assert_eq!(wordpress.website(), "https://wordpress.org");
+        assert_eq!(wordpress.icon(), Some("WordPress.svg"));
+        assert_eq!(wordpress.cpe(), None);
+        let coldfusion = apps_json_data().named("Adobe ColdFusion").unwrap();
+        assert_eq!(coldfusion.cpe(), Some("cpe:/a:adobe:coldfusion"));
+
+        let blogs = apps_json_data().apps_in_category(11);
+        assert!(blogs.iter().any(|app| app.name() == "WordPress"));
+        assert!(blogs
+            .iter()
+            .all(|app| app.categories().iter().any(|c| c.id == 11)));
+
+        let categories = apps_json_data().categories();
+        assert_eq!(categories[0].id, 1);
+        assert_eq!(categories[0].name, "CMS");
+    }
 
     // #[test]
     // fn test_check_app() {
@@ -356,37 +2596,950 @@ mod tests {
         //     "<link "
         // ));
     }
-}
 
-fn one_or_more_strings<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    struct StringOrVec(PhantomData<Vec<String>>);
+    #[test]
+    fn test_match_text_version() {
+        let version =
+            |maybe_regex: &str, text: &str| match_text(maybe_regex, text).unwrap().version;
+        assert_eq!(
+            version(
+                "jquery[.-]([\\d.]*\\d)[^/]*\\.js\\;version:\\1",
+                "/js/jquery-1.9.1.min.js"
+            ),
+            Some(String::from("1.9.1"))
+        );
+        assert_eq!(
+            version(
+                "(?:Apache(?:$|/([\\d.]+)|[^/-])|(?:^|\\b)HTTPD)\\;version:\\1",
+                "Apache"
+            ),
+            None
+        );
+        assert_eq!(
+            version("^(.+)$\\;version:\\1?2+:", "abc"),
+            Some(String::from("2+"))
+        );
+        assert_eq!(
+            version(
+                "([\\d.]+)?/modernizr(?:.([\\d.]+))?.*\\.js\\;version:\\1?\\1:\\2",
+                "/modernizr.2.8.3.js"
+            ),
+            Some(String::from("2.8.3"))
+        );
+        assert_eq!(
+            version("(\\d+)\\.(\\d+)\\.(\\d+)\\;version:\\1.\\2.\\3", "v 4.5.6"),
+            Some(String::from("4.5.6"))
+        );
+        assert_eq!(match_text("foo\\;version:\\1", "bar"), None);
+    }
 
-    impl<'de> de::Visitor<'de> for StringOrVec {
-        type Value = Vec<String>;
+    fn raw_data(html: &str) -> Arc<RawData> {
+        Arc::new(RawData {
+            url: String::from("https://example.com/"),
+            final_url: String::from("https://example.com/"),
+            headers: reqwest::header::HeaderMap::new(),
+            cookies: vec![],
+            meta_tags: HashMap::new(),
+            script_tags: vec![],
+            script_srcs: vec![],
+            html: String::from(html),
+            text: String::new(),
+            css: vec![],
+            stylesheet_hrefs: vec![],
+            links: vec![],
+            js_globals: HashMap::new(),
+            external_scripts: HashMap::new(),
+            external_stylesheets: HashMap::new(),
+            dom: HashMap::new(),
+        })
+    }
 
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("string or list of strings")
-        }
+    /// Compiles a single app named "Test" and returns a function checking a page against it
+    fn checker(app_json: &str) -> impl Fn(Arc<RawData>) -> Option<Tech> {
+        let mut app: App = serde_json::from_str(app_json).unwrap();
+        app.name = String::from("Test");
+        let mut apps_json_data = AppsJsonData {
+            apps: HashMap::new(),
+            categories: HashMap::new(),
+        };
+        apps_json_data.apps.insert(app.name.clone(), app);
+        let ruleset = CompiledRuleset::compile(&apps_json_data);
+        assert!(ruleset.errors().is_empty());
 
-        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(vec![value.to_owned()])
+        move |raw_data| {
+            let prefilter = ruleset.prefilter(&raw_data);
+            apps_json_data.apps["Test"].check(&ruleset, raw_data, &prefilter)
         }
+    }
 
-        fn visit_seq<S>(self, visitor: S) -> Result<Self::Value, S::Error>
-        where
-            S: de::SeqAccess<'de>,
+    #[test]
+    fn test_script_srcs() {
+        let page_url = Url::parse("https://example.com/blog/post.html").unwrap();
+        let srcs = |html: &str| script_srcs(&Html::parse_fragment(html), &page_url);
+
+        assert_eq!(
+            srcs(
+                r#"<script src="js/app.js"></script>
+                <script>var inline = 1;</script>
+                <script src="//cdn.example.net/jquery.min.js"></script>"#
+            ),
+            vec![
+                "https://example.com/blog/js/app.js",
+                "https://cdn.example.net/jquery.min.js"
+            ]
+        );
+        assert_eq!(
+            srcs(r#"<base href="/static/"><script src="app.js"></script>"#),
+            vec!["https://example.com/static/app.js"]
+        );
+    }
+
+    #[test]
+    fn test_page_text() {
+        let html = Html::parse_document(
+            r#"<html><head><title>Home</title><style>p { color: red }</style></head>
+            <body><h1>Welcome</h1><script>var x = 1;</script><p>Powered by <b>Acme</b></p></body></html>"#,
+        );
+        assert_eq!(page_text(&html), "Welcome Powered by Acme");
+    }
+
+    #[test]
+    fn test_check_confidence() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "http://example.com",
+                "html": ["foo\\;confidence:25", "bar\\;confidence:50", "baz"]
+            }"#,
+        );
+        assert_eq!(check(raw_data("nothing")), None);
+        assert_eq!(check(raw_data("foo")).unwrap().confidence, 25);
+        assert_eq!(check(raw_data("foo bar")).unwrap().confidence, 75);
+        assert_eq!(check(raw_data("foo bar baz")).unwrap().confidence, 100);
+        assert_eq!(check(raw_data("baz")).unwrap().confidence, 100);
+    }
+
+    #[test]
+    fn test_check_js() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "https://example.com",
+                "js": {
+                    "List": "\\;confidence:50",
+                    "jQuery.fn.jquery": "([\\d.]+)\\;version:\\1"
+                }
+            }"#,
+        );
+        let page_url = Url::parse("https://example.com/").unwrap();
+        let raw_data = |html: &str| {
+            let headers = reqwest::header::HeaderMap::new();
+            Arc::new(RawData::new(
+                "",
+                &page_url,
+                headers,
+                vec![],
+                String::from(html),
+            ))
+        };
+
+        // the name of the global, not any text that contains it
+        assert_eq!(
+            check(raw_data(
+                "<script>var myList = []; listItems.push(1)</script>"
+            )),
+            None
+        );
+        assert_eq!(
+            check(raw_data(
+                r#"<script type="text/template">var List = 1</script>"#
+            )),
+            None
+        );
+        let tech = check(raw_data("<script>var List = function () {}</script>")).unwrap();
+        assert_eq!((tech.confidence, tech.version), (50, None));
+
+        let tech = check(raw_data(
+            "<script>\n jQuery.fn = jQuery.prototype = { jquery: '3.5.1' };\n</script>",
+        ))
+        .unwrap();
+        assert_eq!(tech.version, Some(String::from("3.5.1")));
+        assert_eq!(tech.evidence[0].matched, "3.5.1");
+        // the offset of the property, in the script
+        assert_eq!(tech.evidence[0].offset, 35);
+        // a value that doesn't match the pattern isn't a hit
+        assert_eq!(
+            check(raw_data("<script>jQuery.fn = { jquery: 'none' }</script>")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_check_dom() {
+        let engine = Engine::from_str(
+            r##"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "Root": { "cats": [1], "website": "", "dom": ["body > #root", "#app"] },
+                    "Builder": {
+                        "cats": [1],
+                        "website": "",
+                        "dom": {
+                            "div.builder": {
+                                "text": "Built with Builder ([\\d.]+)\\;version:\\1",
+                                "attributes": { "data-theme": "\\;confidence:25" }
+                            },
+                            "link[href*='builder']": { "exists": "\\;confidence:50" },
+                            "#builder": { "properties": { "_builder": "" } }
+                        }
+                    }
+                }
+            }"##,
+        )
+        .unwrap();
+        assert!(engine.ruleset().errors().is_empty());
+        let names = |html: &str| {
+            let techs = engine.check_sync(raw_data(html)).techs;
+            techs.into_iter().map(|t| t.name).collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("<div id='root'></div>"), vec!["Root"]);
+        assert!(names("<div><div id='root'></div></div>").is_empty());
+        assert!(names("<p>#root #app div.builder</p>").is_empty());
+
+        let techs = engine
+            .check_sync(raw_data(
+                r#"<div class="builder" data-theme="dark">Built with Builder 2.1</div>
+                <div class="builder">Built with Builder 2.1.4</div>"#,
+            ))
+            .techs;
+        assert_eq!(techs[0].version, Some(String::from("2.1")));
+        assert_eq!(techs[0].confidence, 100);
+        // the first element that gives a version
+        let evidence = &techs[0].evidence;
+        assert!(evidence
+            .iter()
+            .any(|e| e.matched == "Built with Builder 2.1"));
+        assert_eq!(evidence[0].key, Some(String::from("div.builder")));
+
+        let techs = engine
+            .check_sync(raw_data(
+                r#"<link rel="stylesheet" href="/builder.css"><div data-theme="x" class="builder"></div>"#,
+            ))
+            .techs;
+        assert_eq!((techs[0].confidence, techs[0].version.clone()), (75, None));
+        // the properties of the elements need a browser
+        assert!(names("<div id='builder'></div>").is_empty());
+        assert_eq!(
+            engine
+                .apps_json_data()
+                .named("Builder")
+                .unwrap()
+                .unchecked_fields(),
+            vec!["dom"]
+        );
+
+        // a page that wasn't prepared gets the elements an app needs when it's checked
+        let check = checker(r#"{ "cats": [1], "website": "", "dom": "body > #root" }"#);
+        assert!(check(raw_data("<div id='root'></div>")).is_some());
+        // and the elements can be selected along with the rest of the page
+        let page = RawData::with_dom(
+            "https://example.com/",
+            &Url::parse("https://example.com/").unwrap(),
+            reqwest::header::HeaderMap::new(),
+            vec![],
+            String::from("<div id='root'></div><div class='builder'>Built with Builder 3</div>"),
+            engine.ruleset(),
+        );
+        assert_eq!(page.dom["body > #root"].len(), 1);
+        assert_eq!(page.dom["div.builder"][0].text, "Built with Builder 3");
+        assert!(engine.ruleset().select_dom(&page).is_empty());
+    }
+
+    #[test]
+    fn test_check_script() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "https://jquery.com",
+                "script": [
+                    "jquery[.-]([\\d.]*\\d)[^/]*\\.js\\;version:\\1",
+                    "jquery.*\\.js(?:\\?ver(?:sion)?=([\\d.]+))?\\;version:\\1"
+                ]
+            }"#,
+        );
+        let raw_data = |srcs: &[&str]| {
+            let mut raw_data = RawData::clone(&raw_data(""));
+            raw_data.script_srcs = srcs.iter().map(|s| String::from(*s)).collect();
+            Arc::new(raw_data)
+        };
+
+        assert_eq!(check(raw_data(&["https://example.com/app.js"])), None);
+        let tech = check(raw_data(&[
+            "https://example.com/js/jquery-ui.js",
+            "https://example.com/js/jquery-1.12.4.min.js",
+        ]))
+        .unwrap();
+        assert_eq!(tech.version, Some(String::from("1.12.4")));
+        assert_eq!(tech.confidence, 100);
+    }
+
+    #[test]
+    fn test_check_assets() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "https://example.com",
+                "scripts": "Acme UI v([\\d.]+)\\;version:\\1",
+                "css": "\\.acme-grid\\;confidence:50",
+                "js": { "AcmeUI.version": "" }
+            }"#,
+        );
+        let page_url = Url::parse("https://example.com/").unwrap();
+        let mut page = RawData::new(
+            "https://example.com/",
+            &page_url,
+            reqwest::header::HeaderMap::new(),
+            vec![],
+            String::from(
+                r#"<link rel="preload stylesheet" href="/acme.css"><script src="/acme.js"></script>"#,
+            ),
+        );
+        assert_eq!(page.stylesheet_hrefs, vec!["https://example.com/acme.css"]);
+        assert_eq!(check(Arc::new(page.clone())), None);
+
+        let asset = |url: &str, content: &str| {
+            let mut assets = HashMap::new();
+            assets.insert(String::from(url), String::from(content));
+            assets
+        };
+        page.add_assets(
+            asset(
+                "https://example.com/acme.js",
+                "/*! Acme UI v2.3.1 */ window.AcmeUI = { version: '2.3.1' };",
+            ),
+            asset(
+                "https://example.com/acme.css",
+                ".acme-grid { display: grid }",
+            ),
+        );
+        let tech = check(Arc::new(page)).unwrap();
+        assert_eq!(tech.version, Some(String::from("2.3.1")));
+        assert_eq!(tech.confidence, 100);
+        let keys = tech
+            .evidence
+            .iter()
+            .map(|e| (e.field.as_str(), e.key.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                ("js", Some("AcmeUI.version")),
+                ("scripts", Some("https://example.com/acme.js")),
+                ("css", Some("https://example.com/acme.css")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_url() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "https://www.shopify.com",
+                "url": "^https?://.+\\.myshopify\\.com\\;confidence:50"
+            }"#,
+        );
+        let raw_data = |url: &str, final_url: &str| {
+            let mut raw_data = RawData::clone(&raw_data(""));
+            raw_data.url = String::from(url);
+            raw_data.final_url = String::from(final_url);
+            Arc::new(raw_data)
+        };
+
+        assert_eq!(
+            check(raw_data("https://example.com/", "https://example.com/")),
+            None
+        );
+        let tech = check(raw_data("http://shop.com/", "https://shop.myshopify.com/")).unwrap();
+        assert_eq!(tech.confidence, 50);
+    }
+
+    #[test]
+    fn test_check_evidence() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "https://wordpress.org",
+                "html": "<link rel=[\"']stylesheet[\"'] [^>]+wp-(?:content|includes)",
+                "meta": { "generator": "WordPress ?([\\d.]+)?\\;version:\\1" }
+            }"#,
+        );
+        let mut raw_data = RawData::clone(&raw_data(
+            r#"<html><link rel="stylesheet" href="/wp-content/style.css"></html>"#,
+        ));
+        raw_data
+            .meta_tags
+            .insert(String::from("generator"), String::from("WordPress 5.2.1"));
+
+        let tech = check(Arc::new(raw_data)).unwrap();
+        assert_eq!(tech.version, Some(String::from("5.2.1")));
+        assert_eq!(
+            tech.evidence,
+            vec![
+                Evidence {
+                    field: String::from("html"),
+                    key: None,
+                    pattern: String::from(
+                        r#"<link rel=["']stylesheet["'] [^>]+wp-(?:content|includes)"#
+                    ),
+                    matched: String::from(r#"<link rel="stylesheet" href="/wp-content"#),
+                    offset: 6,
+                },
+                Evidence {
+                    field: String::from("meta"),
+                    key: Some(String::from("generator")),
+                    pattern: String::from(r"WordPress ?([\d.]+)?\;version:\1"),
+                    matched: String::from("WordPress 5.2.1"),
+                    offset: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_cpe() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "https://jquery.com",
+                "cpe": "cpe:/a:jquery:jquery",
+                "script": "jquery[.-]([\\d.]*\\d)[^/]*\\.js\\;version:\\1"
+            }"#,
+        );
+        let mut raw_data = RawData::clone(&raw_data(""));
+        raw_data.script_srcs = vec![String::from("/jquery-1.12.4.min.js")];
+        assert_eq!(
+            check(Arc::new(raw_data)).unwrap().cpe,
+            Some(String::from("cpe:2.3:a:jquery:jquery:1.12.4:*:*:*:*:*:*:*"))
+        );
+
+        assert!(apps_json_data()
+            .apps()
+            .iter()
+            .filter_map(|app| app.cpe())
+            .all(|cpe| Cpe::parse(cpe).is_ok()));
+    }
+
+    #[test]
+    fn test_compiled_ruleset() {
+        let apps_json_data = AppsJsonData::parse(
+            r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "Apache": { "cats": [1], "website": "", "headers": { "Server": "Apache" } },
+                    "Broken": { "cats": [1], "website": "", "html": "(y", "cookies": { "ok": "" } },
+                    "React": { "cats": [1], "website": "", "html": "data-reactroot" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let ruleset = CompiledRuleset::compile(&apps_json_data);
+
+        assert_eq!(ruleset.errors().len(), 1);
+        assert_eq!(ruleset.errors()[0].app, "Broken");
+        assert_eq!(ruleset.errors()[0].field, "html");
+
+        let candidates = |raw_data: &RawData| {
+            let mut apps = ruleset
+                .prefilter(raw_data)
+                .apps
+                .into_iter()
+                .collect::<Vec<_>>();
+            apps.sort();
+            apps
+        };
+
+        let mut raw_data = RawData::clone(&raw_data(""));
+        assert!(candidates(&raw_data).is_empty());
+
+        raw_data.html = String::from("<div data-reactroot>");
+        assert_eq!(candidates(&raw_data), vec!["React"]);
+
+        raw_data.headers.insert(
+            reqwest::header::SERVER,
+            reqwest::header::HeaderValue::from_static("Apache"),
+        );
+        assert_eq!(candidates(&raw_data), vec!["Apache", "React"]);
+    }
+
+    #[test]
+    fn test_engine() {
+        let engine: Engine = r#"{
+            "categories": { "1": { "name": "CMS", "priority": 1 } },
+            "apps": {
+                "In-house CMS": { "cats": [1], "website": "", "html": "<div id=\"inhouse-root\"" }
+            }
+        }"#
+        .parse()
+        .unwrap();
+        let raw_data = raw_data(r#"<div id="inhouse-root"></div>"#);
+
+        let detections = engine.check_sync(raw_data.clone());
+        assert_eq!(detections.techs.len(), 1);
+        assert_eq!(detections.techs[0].name, "In-house CMS");
+        assert_eq!(detections.techs[0].category, "CMS");
+
+        let detections = Engine::embedded().check_sync(raw_data);
+        assert!(detections.techs.iter().all(|t| t.name != "In-house CMS"));
+
+        assert!(matches!(
+            Engine::from_path("/no/such/apps.json"),
+            Err(WappError::Ruleset(_))
+        ));
+        assert!(matches!(
+            "{ not json".parse::<Engine>(),
+            Err(WappError::Ruleset(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_content() {
+        let check = checker(
+            r#"{
+                "cats": [1],
+                "website": "https://example.com",
+                "scripts": "acmeAnalytics\\.init\\(\\{version:'([\\d.]+)'\\;version:\\1",
+                "text": "Powered by Acme\\;confidence:50",
+                "css": "\\.acme-banner\\;confidence:25"
+            }"#,
+        );
+        let raw_data = |script_tags: &[&str], text: &str, css: &[&str]| {
+            let mut raw_data = RawData::clone(&raw_data(""));
+            raw_data.script_tags = script_tags.iter().map(|s| String::from(*s)).collect();
+            raw_data.text = String::from(text);
+            raw_data.css = css.iter().map(|s| String::from(*s)).collect();
+            Arc::new(raw_data)
+        };
+
+        assert_eq!(check(raw_data(&[], "Powered by Other", &[])), None);
+        let tech = check(raw_data(&[], "Home | Powered by Acme", &[])).unwrap();
+        assert_eq!(tech.confidence, 50);
+
+        let tech = check(raw_data(
+            &["<script>acmeAnalytics.init({version:'2.1.0'})</script>"],
+            "",
+            &["body { margin: 0 }", ".acme-banner { color: red }"],
+        ))
+        .unwrap();
+        assert_eq!(tech.version, Some(String::from("2.1.0")));
+        assert_eq!(tech.confidence, 100);
+        let fields = tech
+            .evidence
+            .iter()
+            .map(|e| e.field.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, vec!["scripts", "css"]);
+    }
+
+    #[test]
+    fn test_requires() {
+        let engine: Engine = r#"{
+            "categories": {
+                "1": { "name": "CMS", "priority": 1 },
+                "2": { "name": "Plugins", "priority": 2 },
+                "3": { "name": "Themes", "priority": 2 }
+            },
+            "apps": {
+                "Acme CMS": { "cats": [1], "website": "", "html": "acme-cms" },
+                "Acme SEO": { "cats": [2], "website": "", "html": "acme-seo", "requires": "Acme CMS" },
+                "Acme Theme": { "cats": [3], "website": "", "html": "acme-theme", "requiresCategory": 2 },
+                "Acme Shop": { "cats": [3], "website": "", "html": "acme-shop", "implies": "Acme Payments" },
+                "Acme Payments": { "cats": [2], "website": "", "requires": "Acme CMS", "implies": "Acme Theme" }
+            }
+        }"#
+        .parse()
+        .unwrap();
+        let names = |html: &str| {
+            let mut names = engine
+                .check_sync(raw_data(html))
+                .techs
+                .into_iter()
+                .map(|t| t.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        assert_eq!(
+            names("acme-cms acme-seo acme-theme"),
+            vec!["Acme CMS", "Acme SEO", "Acme Theme"]
+        );
+        // without the CMS, the plugin goes, and with it the theme that needs a plugin
+        assert_eq!(names("acme-seo acme-theme"), Vec::<String>::new());
+        assert_eq!(names("acme-cms acme-theme"), vec!["Acme CMS"]);
+        // an implied tech needs what it requires too, and what it implies goes with it
+        assert_eq!(names("acme-shop"), vec!["Acme Shop"]);
+        assert_eq!(
+            names("acme-shop acme-cms"),
+            vec!["Acme CMS", "Acme Payments", "Acme Shop", "Acme Theme"]
+        );
+    }
+
+    #[test]
+    fn test_technologies_format() {
+        let dir = std::env::temp_dir().join(format!("wappalyzer-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("technologies")).unwrap();
+        std::fs::write(
+            dir.join("categories.json"),
+            r#"{ "1": { "groups": [3], "name": "CMS", "priority": 1 } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("technologies/a.json"),
+            r##"{
+                "Acme CMS": {
+                    "cats": [1],
+                    "website": "https://acme.example",
+                    "description": "Acme CMS is a content management system.",
+                    "saas": true,
+                    "oss": false,
+                    "pricing": ["low", "recurring"],
+                    "meta": { "generator": ["^Acme CMS ([\\d.]+)\\;version:\\1", "^Acme"] },
+                    "scriptSrc": "/acme\\.js",
+                    "url": ["\\.acme\\.example", "acme-cms\\.net"],
+                    "dom": "#acme-root",
+                    "dns": { "TXT": "acme-verification" }
+                }
+            }"##,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("technologies/_.json"),
+            r#"{ "_Acme Legacy": { "cats": [1], "website": "", "html": "acme-legacy" } }"#,
+        )
+        .unwrap();
+
+        let apps_json_data = AppsJsonData::from_path(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(apps_json_data.apps().len(), 2);
+        let app = apps_json_data.named("Acme CMS").unwrap();
+        assert_eq!(app.category_name(), "CMS");
+        assert_eq!(
+            app.description(),
+            Some("Acme CMS is a content management system.")
+        );
+        assert_eq!((app.saas(), app.oss()), (Some(true), Some(false)));
+        assert_eq!(app.pricing(), ["low", "recurring"]);
+        assert_eq!(app.script, vec!["/acme\\.js"]);
+        assert_eq!(app.meta["generator"].len(), 2);
+        assert_eq!(app.url.len(), 2);
+        assert_eq!(app.unchecked_fields(), vec!["dns"]);
+
+        // the rules that can't be checked are kept when the apps are written back
+        let json = serde_json::to_value(app).unwrap();
+        assert_eq!(json["dom"], "#acme-root");
+        assert_eq!(json["dns"]["TXT"], serde_json::json!(["acme-verification"]));
+
+        let engine = Engine::new(apps_json_data);
+        assert!(engine.ruleset().errors().is_empty());
+        let mut raw_data = RawData::clone(&raw_data(""));
+        raw_data
+            .meta_tags
+            .insert(String::from("generator"), String::from("Acme CMS 4.2"));
+        let techs = engine.check_sync(Arc::new(raw_data)).techs;
+        assert_eq!(techs[0].version, Some(String::from("4.2")));
+
+        // the single-file version of the format
+        let apps_json_data: AppsJsonData = r#"{
+            "categories": { "1": { "name": "CMS", "priority": 1 } },
+            "technologies": { "Acme CMS": { "cats": [1], "website": "" } }
+        }"#
+        .parse()
+        .unwrap();
+        assert!(apps_json_data.named("Acme CMS").is_some());
+        assert!(matches!(
+            AppsJsonData::from_dir("/no/such/dir"),
+            Err(WappError::Ruleset(_))
+        ));
+    }
+
+    #[test]
+    fn test_regex_fallback() {
+        // lookahead
+        let adplan = "^https?://(?!o\\.)\\w+\\.advg\\.jp/";
+        assert!(check_text(adplan, "https://cdn.advg.jp/"));
+        assert!(!check_text(adplan, "https://o.advg.jp/"));
+        // backreference
+        assert!(check_text("<(b|i)>new</\\1>", "<i>new</i>"));
+        assert!(!check_text("<(b|i)>new</\\1>", "<i>new</b>"));
+        // JavaScript syntax
+        assert!(check_text("foo[^]{0,3}bar", "foo\nxbar"));
+        let sentry = "Raven\\.config\\('[^']*', {\\s+release: '([0-9\\.]+)'\\;version:\\1";
+        let version = match_text(sentry, "Raven.config('key', {\n  release: '3.2.1'")
+            .unwrap()
+            .version;
+        assert_eq!(version, Some(String::from("3.2.1")));
+
+        assert_eq!(from_javascript("a{2,}[{^]]{b}"), "a{2,}[{^]]\\{b\\}");
+        assert_eq!(from_javascript("x(?!y.)+z[^]"), "x(?!y.)z[\\s\\S]");
+
+        // every app of the embedded apps.json gets its patterns back
+        let counts = compiled_ruleset().fallback_counts();
+        assert!(counts.apps_before > 0);
+        assert_eq!((counts.apps_after, counts.patterns_after), (0, 0));
+        assert!(compiled_ruleset().errors().is_empty());
+    }
+
+    #[test]
+    fn test_overlays() {
+        let overlay: Overlay = r#"{
+            "categories": { "1000": { "name": "In-house", "priority": 1 } },
+            "apps": {
+                "In-house CMS": { "cats": [1000, 2000], "website": "", "html": "inhouse-root" },
+                "jQuery": { "cats": [59], "website": "https://jquery.com", "html": "jQuery" },
+                "AngularJS": { "cats": [12], "website": "https://angularjs.org" }
+            },
+            "disable": ["ExtJS", "Not an app"]
+        }"#
+        .parse()
+        .unwrap();
+        let renaming: Overlay = r#"{ "categories": { "1": { "name": "Sites", "priority": 1 } } }"#
+            .parse()
+            .unwrap();
+
+        let (merged, warnings) = AppsJsonData::embedded().with_overlays(vec![overlay, renaming]);
+        assert_eq!(
+            warnings,
+            vec![
+                OverlayWarning::AppReplaced {
+                    app: String::from("AngularJS")
+                },
+                OverlayWarning::AppReplaced {
+                    app: String::from("jQuery")
+                },
+                OverlayWarning::UnknownDisabledApp {
+                    app: String::from("Not an app")
+                },
+                OverlayWarning::CategoryReplaced {
+                    id: 1,
+                    name: String::from("CMS"),
+                    by: String::from("Sites")
+                },
+                OverlayWarning::UnknownCategory {
+                    app: String::from("In-house CMS"),
+                    id: 2000
+                },
+            ]
+        );
+
+        let in_house = merged.named("In-house CMS").unwrap();
+        assert_eq!(in_house.category_name(), "In-house");
+        assert_eq!(merged.named("jQuery").unwrap().html, vec!["jQuery"]);
+        assert!(merged.named("ExtJS").is_none());
+        assert_eq!(merged.named("WordPress").unwrap().category_name(), "Sites");
+        assert!(apps_json_data().named("ExtJS").is_some());
+    }
+
+    #[test]
+    fn test_html_prefilter() {
+        let with_set = Engine::embedded();
+        assert!(with_set.ruleset().html_set.is_some());
+        let without_set = Engine::embedded().without_html_prefilter();
+
+        let raw_data = raw_data(
+            r#"<html ng-app="x"><div class="sf-toolbar-block"></div>
+            <link rel="stylesheet" href="/wp-content/themes/x/style.css?ver=5.2.1">
+            <meta name="generator" content="WordPress 5.2.1"></html>"#,
+        );
+        let names = |detections: Detections| {
+            let mut names = detections
+                .techs
+                .into_iter()
+                .map(|t| (t.name, t.version))
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let detected = names(with_set.check_sync(raw_data.clone()));
+        assert!(!detected.is_empty());
+        assert_eq!(detected, names(without_set.check_sync(raw_data)));
+    }
+
+    #[test]
+    fn test_categories() {
+        // WordPress is [1, 11]: CMS (priority 1) and Blogs (priority 1)
+        let wordpress = Tech::from(apps_json_data().apps.get("WordPress").unwrap());
+        assert_eq!(wordpress.category, "CMS");
+        assert_eq!(
+            wordpress
+                .categories
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["CMS", "Blogs"]
+        );
+
+        // Shopify is [6]
+        let shopify = Tech::from(apps_json_data().apps.get("Shopify").unwrap());
+        assert_eq!(shopify.categories.len(), 1);
+        assert_eq!(shopify.categories[0].id, 6);
+
+        // styled-components is [12, 47], but 47 (priority 2) comes before 12 (priority 8)
+        let styled = Tech::from(apps_json_data().apps.get("styled-components").unwrap());
+        assert_eq!(styled.category, "Dev Tools");
+        assert_eq!(styled.categories[1].id, 12);
+    }
+
+    #[test]
+    fn test_apply_implies() {
+        let apps_json_data = AppsJsonData::parse(
+            r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "WordPress": { "cats": [1], "website": "", "implies": ["PHP", "MySQL\\;confidence:50"] },
+                    "PHP": { "cats": [1], "website": "", "implies": "Zend" },
+                    "MySQL": { "cats": [1], "website": "" },
+                    "Zend": { "cats": [1], "website": "", "implies": "PHP" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let wordpress = Tech::from(apps_json_data.apps.get("WordPress").unwrap());
+
+        let techs = apps_json_data.apply_implies(vec![wordpress], &HashSet::new());
+        let tech = |name: &str| techs.iter().find(|t| t.name == name).unwrap();
+
+        assert_eq!(techs.len(), 4);
+        assert_eq!(tech("PHP").implied_by, vec!["WordPress", "Zend"]);
+        assert_eq!(tech("PHP").confidence, 100);
+        assert_eq!(tech("MySQL").implied_by, vec!["WordPress"]);
+        assert_eq!(tech("MySQL").confidence, 50);
+        assert_eq!(tech("Zend").implied_by, vec!["PHP"]);
+        assert!(tech("WordPress").implied_by.is_empty());
+
+        // whatever the order the techs are visited in, an implied tech gets the final
+        // confidence of the techs implying it: here MySQL's, once raised by Drupal
+        let apps_json_data = AppsJsonData::parse(
+            r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "Drupal": { "cats": [1], "website": "", "implies": "MySQL\\;confidence:50" },
+                    "MySQL": { "cats": [1], "website": "", "implies": "Percona" },
+                    "Percona": { "cats": [1], "website": "" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let tech = |name: &str, confidence| Tech {
+            confidence,
+            ..Tech::from(apps_json_data.apps.get(name).unwrap())
+        };
+        for detected in [
+            vec![tech("Drupal", 100), tech("MySQL", 25)],
+            vec![tech("MySQL", 25), tech("Drupal", 100)],
+        ]
+        .iter()
         {
-            Deserialize::deserialize(de::value::SeqAccessDeserializer::new(visitor))
+            let techs = apps_json_data.apply_implies(detected.clone(), &HashSet::new());
+            let confidence = |name: &str| techs.iter().find(|t| t.name == name).unwrap().confidence;
+            assert_eq!(confidence("MySQL"), 75);
+            assert_eq!(confidence("Percona"), 75);
         }
+
+        // implied names are written as regexes upstream, and name apps once unescaped
+        let apps_json_data = AppsJsonData::parse(
+            r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "CppCMS": { "cats": [1], "website": "", "implies": "C\\+\\+" },
+                    "C++": { "cats": [1], "website": "" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let cppcms = Tech::from(apps_json_data.apps.get("CppCMS").unwrap());
+        let techs = apps_json_data.apply_implies(vec![cppcms], &HashSet::new());
+        assert_eq!(techs.len(), 2);
+        assert_eq!(techs[1].name, "C++");
+        assert_eq!(techs[1].implied_by, vec!["CppCMS"]);
     }
 
-    deserializer.deserialize_any(StringOrVec(PhantomData))
+    #[test]
+    fn test_apply_excludes() {
+        let apps_json_data = AppsJsonData::parse(
+            r#"{
+                "categories": { "1": { "name": "CMS", "priority": 1 } },
+                "apps": {
+                    "Angular": { "cats": [1], "website": "", "excludes": ["AngularJS", "AngularDart"] },
+                    "AngularJS": { "cats": [1], "website": "", "excludes": ["Angular", "AngularDart"] },
+                    "AngularDart": { "cats": [1], "website": "", "excludes": ["Angular", "AngularJS"] }
+                }
+            }"#,
+        )
+        .unwrap();
+        let tech = |name: &str, confidence: u32| Tech {
+            confidence,
+            ..Tech::from(apps_json_data.apps.get(name).unwrap())
+        };
+
+        let detections = apps_json_data.apply_excludes(vec![
+            tech("AngularJS", 100),
+            tech("Angular", 100),
+            tech("AngularDart", 50),
+        ]);
+        assert_eq!(detections.techs, vec![tech("Angular", 100)]);
+        assert_eq!(
+            detections.excluded,
+            vec![
+                Exclusion {
+                    name: String::from("AngularJS"),
+                    excluded_by: String::from("Angular"),
+                },
+                Exclusion {
+                    name: String::from("AngularDart"),
+                    excluded_by: String::from("Angular"),
+                },
+            ]
+        );
+
+        let detections = apps_json_data.apply_excludes(vec![tech("AngularDart", 100)]);
+        assert_eq!(detections.techs.len(), 1);
+        assert!(detections.excluded.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_excluded_implies() {
+        let engine: Engine = r#"{
+            "categories": { "1": { "name": "CMS", "priority": 1 } },
+            "apps": {
+                "Acme CMS": { "cats": [1], "website": "", "html": "acme-cms", "excludes": "Acme Lite" },
+                "Acme Lite": { "cats": [1], "website": "", "html": "acme-lite", "implies": ["Acme Cache", "PHP"] },
+                "Acme Blog": { "cats": [1], "website": "", "html": "acme-blog", "implies": "PHP" },
+                "Acme Cache": { "cats": [1], "website": "" },
+                "PHP": { "cats": [1], "website": "" }
+            }
+        }"#
+        .parse()
+        .unwrap();
+
+        // the cache is only implied by the excluded Acme Lite, PHP also by Acme Blog
+        let detections = engine.check_sync(raw_data("acme-cms acme-lite acme-blog"));
+        let names = detections
+            .techs
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Acme Blog", "Acme CMS", "PHP"]);
+        assert_eq!(detections.techs[2].implied_by, vec!["Acme Blog"]);
+        assert_eq!(
+            detections.excluded,
+            vec![Exclusion {
+                name: String::from("Acme Lite"),
+                excluded_by: String::from("Acme CMS"),
+            }]
+        );
+    }
 }
 
 /*